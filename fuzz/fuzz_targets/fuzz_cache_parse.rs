@@ -0,0 +1,16 @@
+#![no_main]
+
+use gtk_icon_cache::GtkIconCache;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(cache) = GtkIconCache::with_bytes(data) {
+        let _ = cache.validate();
+        let _ = cache.list_directories();
+
+        for name in cache.list_all_icons() {
+            let _ = cache.lookup(&name);
+            let _ = cache.lookup_with_flags(&name);
+        }
+    }
+});
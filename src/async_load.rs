@@ -0,0 +1,84 @@
+use CacheError;
+use GtkIconCache;
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use tokio::task::JoinHandle;
+
+///
+/// Future loading a cache file, returned by `GtkIconCache::with_file_path_async`.
+///
+/// The file is opened once and read on the tokio blocking pool, parsing is
+/// done when the future is polled after reading finishes.
+///
+pub struct LoadFuture {
+    path: PathBuf,
+    state: State,
+}
+
+enum State {
+    Start,
+    Reading(JoinHandle<io::Result<(Option<SystemTime>, Vec<u8>)>>),
+    Done,
+}
+
+impl LoadFuture {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path, state: State::Start }
+    }
+}
+
+///
+/// Modification time and content of the file at `path`, both taken from
+/// the same open file.
+///
+fn read_file(path: PathBuf) -> io::Result<(Option<SystemTime>, Vec<u8>)> {
+    let mut file = File::open(path)?;
+    let last_modified = file.metadata()?.modified().ok();
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    Ok((last_modified, data))
+}
+
+impl Future for LoadFuture {
+    type Output = Result<GtkIconCache, CacheError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // spawned when first polled, so the future can be created outside
+        // of the runtime
+        if let State::Start = self.state {
+            let path = self.path.clone();
+            self.state = State::Reading(::tokio::task::spawn_blocking(move || read_file(path)));
+        }
+
+        let r = match self.state {
+            State::Reading(ref mut f) => match Pin::new(f).poll(cx) {
+                Poll::Ready(r) => r,
+                Poll::Pending => return Poll::Pending,
+            },
+            State::Start => unreachable!(),
+            State::Done => panic!("LoadFuture polled after completion"),
+        };
+        self.state = State::Done;
+
+        let r = r.unwrap_or_else(|e| Err(io::Error::other(e)))
+            .map_err(CacheError::from)
+            .and_then(|(last_modified, data)| {
+                let mut r = GtkIconCache::with_bytes_owned(data)?;
+                r.source_path = Some(self.path.clone());
+                r.last_modified = last_modified;
+
+                Ok(r)
+            });
+
+        Poll::Ready(r)
+    }
+}
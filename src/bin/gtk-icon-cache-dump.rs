@@ -0,0 +1,110 @@
+//!
+//! Dump directories of a gtk-icon-cache file, and look up an icon in it.
+//!
+//! ```text
+//! gtk-icon-cache-dump [--json] <cache-file> [icon-name]
+//! ```
+//!
+
+extern crate gtk_icon_cache;
+
+use gtk_icon_cache::GtkIconCache;
+
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: gtk-icon-cache-dump [--json] <cache-file> [icon-name]";
+
+fn main() {
+    let mut json = false;
+    let mut args = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => args.push(arg),
+        }
+    }
+
+    if args.is_empty() || args.len() > 2 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let icon_cache = match GtkIconCache::with_file_path(&args[0]) {
+        Ok(cache) => cache,
+        Err(e) => {
+            eprintln!("{}: {}", args[0], e);
+            process::exit(1);
+        },
+    };
+
+    let dirs = icon_cache.list_directories();
+    let icon = args.get(1).map(|name| (name, icon_cache.lookup(name)));
+
+    if json {
+        print_json(&dirs, icon);
+    } else {
+        print_text(&dirs, icon);
+    }
+}
+
+fn print_text(dirs: &[&str], icon: Option<(&String, Option<Vec<&str>>)>) {
+    println!("directories:");
+    for dir in dirs {
+        println!("  {}", dir);
+    }
+
+    if let Some((name, found)) = icon {
+        match found {
+            Some(found) => {
+                println!("{}:", name);
+                for dir in found {
+                    println!("  {}", dir);
+                }
+            },
+            None => println!("{}: not found", name),
+        }
+    }
+}
+
+fn print_json(dirs: &[&str], icon: Option<(&String, Option<Vec<&str>>)>) {
+    let dirs: Vec<_> = dirs.iter().map(|x| json_string(x)).collect();
+    let mut r = format!("{{\"directories\":[{}]", dirs.join(","));
+
+    if let Some((name, found)) = icon {
+        let found = match found {
+            Some(found) => {
+                let found: Vec<_> = found.iter().map(|x| json_string(x)).collect();
+                format!("[{}]", found.join(","))
+            },
+            None => "null".to_string(),
+        };
+
+        r.push_str(&format!(",\"icon\":{{\"name\":{},\"directories\":{}}}", json_string(name), found));
+    }
+
+    r.push('}');
+    println!("{}", r);
+}
+
+fn json_string(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+
+    r
+}
@@ -0,0 +1,285 @@
+use CacheError;
+use IconCache;
+use LoadOptions;
+use icon_name_hash;
+use parse::{Header, Reader};
+
+use alloc::vec::Vec;
+use core::str;
+
+///
+/// Cache borrowing its data, for short-lived parsing where the data
+/// outlives the cache.
+///
+/// Unlike `GtkIconCache` nothing is copied or reference counted, directory
+/// names are read from the data on every lookup.
+///
+/// ```
+/// use gtk_icon_cache::*;
+///
+/// let data = std::fs::read("test/caches/icon-theme.cache").unwrap();
+/// let icon_cache = GtkIconCacheRef::with_bytes(&data).unwrap();
+///
+/// assert!(icon_cache.lookup("firefox").unwrap().contains(&"apps/64"));
+/// ```
+///
+#[derive(Debug, Clone, Copy)]
+pub struct GtkIconCacheRef<'a> {
+    reader: Reader<'a>,
+    header: Header,
+    hash_fn: Option<fn(&str) -> usize>,
+}
+
+impl<'a> GtkIconCacheRef<'a> {
+    ///
+    /// Create with cache data, which is borrowed for the lifetime of the cache.
+    ///
+    /// * `data` - Cache file content.
+    ///
+    pub fn with_bytes(data: &'a [u8]) -> Result<Self, CacheError> {
+        Self::with_bytes_and_options(data, &LoadOptions::new())
+    }
+
+    ///
+    /// Like `with_bytes`, checking chain lengths, reading numbers and hashing
+    /// icon names as set in `opts`. The mmap advice is ignored as the data
+    /// is not mapped here.
+    ///
+    /// * `data` - Cache file content.
+    /// * `opts` - Load options.
+    ///
+    pub fn with_bytes_and_options(data: &'a [u8], opts: &LoadOptions) -> Result<Self, CacheError> {
+        let reader = Reader::new(data, opts.byte_order);
+        let header = reader.header()?;
+
+        if let Some(max_chain_length) = opts.max_chain_length {
+            reader.check_chain_lengths(&header, max_chain_length)?;
+        }
+
+        Ok(Self { reader, header, hash_fn: opts.hash_fn })
+    }
+
+    fn read_cstring_from(&self, offset: usize) -> Option<&'a str> {
+        match self.reader.cstring(offset)? {
+            b"" => None,
+            bytes => str::from_utf8(bytes).ok(),
+        }
+    }
+
+    fn directory_by_index(&self, idx: usize) -> Option<&'a str> {
+        if idx >= self.header.n_directories {
+            return None;
+        }
+
+        let offset = self.reader.card32(self.header.directory_list_offset + 4 + idx * 4)?;
+        self.read_cstring_from(offset)
+    }
+
+    fn find_list_offset(&self, name: &str) -> Option<usize> {
+        let hash = match self.hash_fn {
+            Some(hash_fn) => hash_fn(name),
+            None => icon_name_hash(name) as usize,
+        };
+        let bucket_index = hash % self.header.n_buckets;
+
+        let mut r = None;
+        self.reader.walk_chain(self.header.hash_offset, bucket_index, |entry_offset, name_offset| {
+            if self.read_cstring_from(name_offset) == Some(name) {
+                r = self.reader.card32(entry_offset + 8);
+                return true;
+            }
+
+            false
+        });
+
+        r
+    }
+}
+
+impl<'a> IconCache for GtkIconCacheRef<'a> {
+    fn lookup(&self, name: &str) -> Option<Vec<&str>> {
+        let list_offset = self.find_list_offset(name)?;
+        let list_len = self.reader.card32(list_offset)?;
+
+        // the whole image list must be readable
+        if list_offset + 4 + 8 * list_len > self.reader.data().len() {
+            warn!("image list at offset {} is out of bounds", list_offset);
+            return None;
+        }
+
+        let mut r = Vec::with_capacity(list_len);
+        for i in 0..list_len {
+            if let Some(dir) = self.reader.card16(list_offset + 4 + 8 * i).and_then(|x| self.directory_by_index(x)) {
+                if !r.contains(&dir) {
+                    r.push(dir);
+                }
+            }
+        }
+
+        // no directory index is in range
+        if r.is_empty() && list_len != 0 {
+            return None;
+        }
+
+        Some(r)
+    }
+
+    fn list_directories(&self) -> Vec<&str> {
+        (0..self.header.n_directories)
+            .filter_map(|i| self.directory_by_index(i))
+            .collect()
+    }
+
+    fn n_buckets(&self) -> usize {
+        self.header.n_buckets
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+
+    use GtkIconCache;
+    use GtkIconCacheBuilder;
+    use GtkIconCacheRef;
+    use IconCache;
+    use ImageTypeFlags;
+    use ByteOrder;
+    use CacheError;
+    use LoadOptions;
+    use MAX_NAME_LEN;
+
+    fn lookup_sorted<C: IconCache>(cache: &C, name: &str) -> Option<Vec<String>> {
+        let mut r: Vec<_> = cache.lookup(name)?.into_iter().map(|x| x.to_string()).collect();
+        r.sort();
+
+        Some(r)
+    }
+
+    #[test]
+    fn test_cache_ref() {
+        let data = include_bytes!("../test/caches/test1.cache");
+        let icon_cache = GtkIconCache::with_bytes(data).unwrap();
+        let cache_ref = GtkIconCacheRef::with_bytes(data).unwrap();
+
+        assert_eq!(cache_ref.n_buckets(), 11);
+        assert_eq!(IconCache::list_directories(&cache_ref), IconCache::list_directories(&icon_cache));
+
+        for name in &["test", "test-48x", "name.with.dot", "deepin-deb-installer", "not-exist", ""] {
+            assert_eq!(lookup_sorted(&cache_ref, name), lookup_sorted(&icon_cache, name));
+            assert_eq!(IconCache::contains(&cache_ref, name), IconCache::contains(&icon_cache, name));
+        }
+    }
+
+    #[test]
+    fn test_cache_ref_huge_image_list() {
+        let mut data = include_bytes!("../test/caches/test1.cache").to_vec();
+        let list_offset = {
+            let cache_ref = GtkIconCacheRef::with_bytes(&data).unwrap();
+            assert!(cache_ref.lookup("test").is_some());
+            cache_ref.find_list_offset("test").unwrap()
+        };
+
+        // patch the image list length of `test`
+        data[list_offset..list_offset + 4].copy_from_slice(&[0xff, 0xff, 0xff, 0xf0]);
+
+        let cache_ref = GtkIconCacheRef::with_bytes(&data).unwrap();
+        assert!(cache_ref.lookup("test").is_none());
+    }
+
+    #[test]
+    fn test_cache_ref_long_name() {
+        let mut data = include_bytes!("../test/caches/test1.cache").to_vec();
+        let name_offset = data.len();
+        data.extend(vec![b'a'; MAX_NAME_LEN + 1]);
+        data.push(0);
+
+        // point the first directory name past the limit
+        let dir_list = GtkIconCacheRef::with_bytes(&data).unwrap().header.directory_list_offset;
+        data[dir_list + 4..dir_list + 8].copy_from_slice(&(name_offset as u32).to_be_bytes());
+
+        let cache_ref = GtkIconCacheRef::with_bytes(&data).unwrap();
+        assert!(cache_ref.directory_by_index(0).is_none());
+    }
+
+    #[test]
+    fn test_cache_ref_invalid() {
+        match GtkIconCacheRef::with_bytes(&[0, 2, 0, 0]) {
+            Err(CacheError::InvalidVersion { major: 2, minor: 0 }) => {},
+            r => panic!("unexpected {:?}", r),
+        }
+
+        match GtkIconCacheRef::with_bytes(&[0, 1]) {
+            Err(CacheError::TruncatedData { offset: 2 }) => {},
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_ref_overlapping_tables() {
+        let data = [
+            0, 1, 0, 0,
+            0, 0, 0, 12,    // hash offset
+            0, 0, 0, 16,    // directory list offset, the first bucket
+            0, 0, 0, 1,     // n_buckets
+            0, 0, 0, 0,     // bucket 0, also n_directories
+        ];
+
+        match GtkIconCacheRef::with_bytes(&data) {
+            Err(CacheError::OverlappingTables { hash_offset: 12, directory_list_offset: 16 }) => {},
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_ref_max_chain_length() {
+        let mut builder = GtkIconCacheBuilder::with_hash_fn(|_| 0);
+        let apps_16 = builder.add_directory("apps/16");
+        for i in 0..3 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+
+        // all icons in the first and only bucket
+        let mut data = builder.build();
+        data[12..16].copy_from_slice(&[0, 0, 0, 1]);
+
+        assert!(GtkIconCacheRef::with_bytes(&data).is_ok());
+        assert!(GtkIconCacheRef::with_bytes_and_options(&data, &LoadOptions::new().max_chain_length(3)).is_ok());
+
+        match GtkIconCacheRef::with_bytes_and_options(&data, &LoadOptions::new().max_chain_length(2)) {
+            Err(CacheError::ChainTooLong { bucket: 0, length: 3 }) => {},
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_ref_little_endian() {
+        let data = [
+            1, 0, 0, 0,
+            12, 0, 0, 0,                // hash offset
+            48, 0, 0, 0,                // directory list offset
+            1, 0, 0, 0,                 // n_buckets
+            20, 0, 0, 0,                // bucket 0
+            0xff, 0xff, 0xff, 0xff,     // entry: next
+            32, 0, 0, 0,                // name offset
+            36, 0, 0, 0,                // image list offset
+            b'f', b'x', 0, 0,
+            1, 0, 0, 0,                 // n_images
+            0, 0, 4, 0,                 // directory index, flags
+            0, 0, 0, 0,                 // image data offset
+            1, 0, 0, 0,                 // n_directories
+            56, 0, 0, 0,
+            b'a', b'p', b'p', 0,
+        ];
+
+        match GtkIconCacheRef::with_bytes(&data) {
+            Err(CacheError::InvalidVersion { major: 256, minor: 0 }) => {},
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let opts = LoadOptions::new().byte_order(ByteOrder::LittleEndian);
+        let cache_ref = GtkIconCacheRef::with_bytes_and_options(&data, &opts).unwrap();
+
+        assert_eq!(cache_ref.list_directories(), vec!["app"]);
+        assert_eq!(cache_ref.lookup("fx").unwrap(), vec!["app"]);
+    }
+}
@@ -0,0 +1,859 @@
+use CacheError;
+use GtkIconCache;
+use ImageTypeFlags;
+use EMPTY_OFFSET;
+use icon_name_hash;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counter making temporary file names unique within the process.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+///
+/// Builder which writes GTK icon cache data.
+///
+/// ```
+/// use gtk_icon_cache::*;
+///
+/// let mut builder = GtkIconCacheBuilder::new();
+/// let apps_48 = builder.add_directory("apps/48");
+/// builder.add_icon("firefox", &[(apps_48, ImageTypeFlags::PNG)]);
+///
+/// let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+/// assert!(icon_cache.lookup("firefox").unwrap().contains(&"apps/48"));
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct GtkIconCacheBuilder {
+    directories: Vec<String>,
+    icons: Vec<(String, Vec<(usize, ImageTypeFlags)>)>,
+    /// Index in `icons` of each icon name.
+    icon_indices: HashMap<String, usize>,
+    hash_fn: fn(&str) -> usize,
+    /// Bucket count for a number of icons, applied when building.
+    bucket_count_fn: fn(usize) -> usize,
+}
+
+impl Default for GtkIconCacheBuilder {
+    fn default() -> Self {
+        Self::with_hash_fn(gtk_hash)
+    }
+}
+
+impl GtkIconCacheBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Create with a custom hash function assigning icons to buckets.
+    ///
+    /// Only caches built with the default `icon_name_hash` can be read by
+    /// GTK, others need the same `LoadOptions::hash_fn` to be read by this
+    /// crate.
+    ///
+    /// * `hash_fn` - hash of an icon name.
+    ///
+    pub fn with_hash_fn(hash_fn: fn(&str) -> usize) -> Self {
+        Self {
+            directories: Vec::new(),
+            icons: Vec::new(),
+            icon_indices: HashMap::new(),
+            hash_fn,
+            bucket_count_fn: bucket_count,
+        }
+    }
+
+    ///
+    /// Use the smallest hash table keeping the load factor below 0.7, the
+    /// table is sized when building so icons added later are counted too.
+    ///
+    pub fn shrink_to_fit(&mut self) {
+        self.bucket_count_fn = shrunk_bucket_count;
+    }
+
+    ///
+    /// Add a directory and return its index, adding an existing directory
+    /// returns the index it already has.
+    ///
+    /// * `dir` - directory name, relative to the theme directory.
+    ///
+    pub fn add_directory(&mut self, dir: &str) -> usize {
+        if let Some(index) = self.directories.iter().position(|x| x == dir) {
+            return index;
+        }
+
+        self.directories.push(dir.to_string());
+        self.directories.len() - 1
+    }
+
+    ///
+    /// Add an icon, adding an existing icon again merges `dirs` into its
+    /// directories, image types of a directory it already has are combined.
+    ///
+    /// * `name` - icon name.
+    /// * `dirs` - indices of directories containing the icon, with image types found there.
+    ///
+    pub fn add_icon(&mut self, name: &str, dirs: &[(usize, ImageTypeFlags)]) {
+        let index = match self.icon_indices.get(name) {
+            Some(&index) => index,
+            None => {
+                self.icon_indices.insert(name.to_string(), self.icons.len());
+                self.icons.push((name.to_string(), dirs.to_vec()));
+                return;
+            }
+        };
+
+        let icon_dirs = &mut self.icons[index].1;
+        for &(dir_index, flags) in dirs {
+            match icon_dirs.iter_mut().find(|x| x.0 == dir_index) {
+                Some(x) => x.1 |= flags,
+                None => icon_dirs.push((dir_index, flags)),
+            }
+        }
+    }
+
+    ///
+    /// Build a cache for an icon theme directory, like `gtk-update-icon-cache` does.
+    ///
+    /// Every `.png`, `.svg`, `.xpm` and `.icon` file in subdirectories of
+    /// `theme_dir` is added, icons are named by file name without extension.
+    ///
+    /// * `theme_dir` - icon theme directory, e.g. `/usr/share/icons/hicolor`.
+    ///
+    pub fn rebuild_from_directory<P: AsRef<Path>>(theme_dir: P) -> Result<GtkIconCache, CacheError> {
+        let mut dirs = BTreeMap::new();
+        scan_directory(theme_dir.as_ref(), "", &mut dirs, &mut Vec::new())?;
+
+        let mut icons: BTreeMap<String, Vec<(usize, ImageTypeFlags)>> = BTreeMap::new();
+        let mut builder = Self::new();
+
+        for (dir, dir_icons) in dirs {
+            let dir_index = builder.add_directory(&dir);
+            for (name, flags) in dir_icons {
+                icons.entry(name).or_default().push((dir_index, flags));
+            }
+        }
+
+        for (name, dirs) in icons {
+            builder.add_icon(&name, &dirs);
+        }
+
+        GtkIconCache::with_bytes_owned(builder.build())
+    }
+
+    ///
+    /// Write cache data to a file, replacing it atomically.
+    ///
+    /// Data is written to a temporary file in the same directory first, then
+    /// renamed to `path`, so readers never see a partially written cache.
+    ///
+    /// * `path` - cache file path.
+    ///
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), CacheError> {
+        let path = path.as_ref();
+        let file_name = path.file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: not a file path", path.display())))?;
+
+        let (mut f, mut guard) = create_temp_file(path, file_name)?;
+
+        let with_path = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", guard.path.display(), e));
+        f.write_all(&self.build()).map_err(with_path)?;
+        f.sync_all().map_err(with_path)?;
+
+        fs::rename(&guard.path, path)?;
+        guard.keep = true;
+
+        Ok(())
+    }
+
+    ///
+    /// Write cache data to `writer`.
+    ///
+    /// Offsets are patched in memory before anything is written, so the
+    /// writer needs no `Seek` and may be e.g. a socket.
+    ///
+    /// * `writer` - destination of cache data.
+    ///
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), CacheError> {
+        writer.write_all(&self.build())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    ///
+    /// Write cache data.
+    ///
+    /// The hash table is sized for the icons added so far on every build,
+    /// there is no need to resize it after adding icons.
+    ///
+    pub fn build(&self) -> Vec<u8> {
+        let n_buckets = (self.bucket_count_fn)(self.icons.len());
+
+        let mut buckets = vec![Vec::new(); n_buckets];
+        for (i, (name, _)) in self.icons.iter().enumerate() {
+            buckets[(self.hash_fn)(name) % n_buckets].push(i);
+        }
+
+        let mut w = Writer { data: Vec::new() };
+
+        // header
+        w.card16(1);
+        w.card16(0);
+        w.card32(0);
+        w.card32(0);
+
+        // hash table
+        let hash_offset = w.offset();
+        w.patch(4, hash_offset);
+        w.card32(n_buckets as u32);
+        for _ in 0..n_buckets {
+            w.card32(EMPTY_OFFSET as u32);
+        }
+
+        for (bucket_index, chain) in buckets.iter().enumerate() {
+            let mut link_offset = hash_offset as usize + 4 + 4 * bucket_index;
+
+            for &i in chain {
+                let (ref name, ref dirs) = self.icons[i];
+
+                let entry_offset = w.offset();
+                w.patch(link_offset, entry_offset);
+                w.card32(EMPTY_OFFSET as u32);
+                w.card32(0);
+                w.card32(0);
+                link_offset = entry_offset as usize;
+
+                let name_offset = w.offset();
+                w.patch(entry_offset as usize + 4, name_offset);
+                w.cstring(name);
+
+                let list_offset = w.offset();
+                w.patch(entry_offset as usize + 8, list_offset);
+                w.card32(dirs.len() as u32);
+                for &(dir_index, flags) in dirs {
+                    w.card16(dir_index as u16);
+                    w.card16(flags.bits());
+                    // no image data
+                    w.card32(0);
+                }
+            }
+        }
+
+        // directory list
+        let directory_list_offset = w.offset();
+        w.patch(8, directory_list_offset);
+        w.card32(self.directories.len() as u32);
+        for _ in &self.directories {
+            w.card32(0);
+        }
+
+        for (i, dir) in self.directories.iter().enumerate() {
+            let dir_offset = w.offset();
+            w.patch(directory_list_offset as usize + 4 + 4 * i, dir_offset);
+            w.cstring(dir);
+        }
+
+        w.data
+    }
+}
+
+///
+/// Collect `(icon_name, dirs)` pairs, e.g. from `GtkIconCache::to_hashmap`,
+/// directories are added as needed. Image types are not known, so icons are
+/// added as `ImageTypeFlags::PNG` images, GTK ignores images without flags.
+///
+impl FromIterator<(String, Vec<String>)> for GtkIconCacheBuilder {
+    fn from_iter<I: IntoIterator<Item = (String, Vec<String>)>>(iter: I) -> Self {
+        let mut builder = Self::new();
+
+        for (name, dirs) in iter {
+            let dirs: Vec<_> = dirs.iter().map(|x| (builder.add_directory(x), ImageTypeFlags::PNG)).collect();
+            builder.add_icon(&name, &dirs);
+        }
+
+        builder
+    }
+}
+
+///
+/// Create a new temporary file next to `path`, named uniquely so concurrent
+/// writers never share it.
+///
+fn create_temp_file(path: &Path, file_name: &OsStr) -> Result<(File, TempFileGuard), CacheError> {
+    loop {
+        let mut tmp_name = file_name.to_os_string();
+        tmp_name.push(format!(".tmp-{}-{}", process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let tmp_path = path.with_file_name(tmp_name);
+
+        match OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+            Ok(f) => return Ok((f, TempFileGuard { path: tmp_path, keep: false })),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", tmp_path.display(), e)).into()),
+        }
+    }
+}
+
+///
+/// Removes a temporary file when dropped, unless it is kept.
+///
+struct TempFileGuard {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for TempFileGuard {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+///
+/// Merge two caches into a new in-memory cache with icons of both, icons
+/// in `a` take precedence over icons with the same name in `b`.
+///
+/// Like `GtkIconCache::sanitize`, icons or directories whose names are not
+/// valid UTF-8 are dropped, as are icons left without any directory.
+///
+/// * `a` - cache with higher priority.
+/// * `b` - cache with lower priority.
+///
+pub fn merge(a: &GtkIconCache, b: &GtkIconCache) -> Result<GtkIconCache, CacheError> {
+    let mut builder = GtkIconCacheBuilder::new();
+    let mut added = HashSet::new();
+
+    for cache in &[a, b] {
+        for (name, dirs) in cache.decoded_icons() {
+            if !added.insert(name) {
+                continue;
+            }
+
+            let dirs: Vec<_> = dirs.into_iter()
+                .map(|(dir, flags)| (builder.add_directory(dir), flags))
+                .collect();
+
+            builder.add_icon(name, &dirs);
+        }
+    }
+
+    GtkIconCache::with_bytes_owned(builder.build())
+}
+
+///
+/// Collect icons of `dir` and its subdirectories into `dirs`, keyed by
+/// directory name relative to the theme directory.
+///
+/// Symlinked directories are followed like `gtk-update-icon-cache` does,
+/// `ancestors` holds canonical paths being scanned to stop at symlink loops.
+///
+fn scan_directory(dir: &Path, relative: &str,
+                  dirs: &mut BTreeMap<String, BTreeMap<String, ImageTypeFlags>>,
+                  ancestors: &mut Vec<PathBuf>) -> Result<(), CacheError> {
+    let canonical = fs::canonicalize(dir)?;
+    if ancestors.contains(&canonical) {
+        warn!("skip {}, symlink loop", dir.display());
+        return Ok(());
+    }
+    ancestors.push(canonical);
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+
+        // follows symlinks, broken links are not directories
+        if fs::metadata(&path).map(|x| x.is_dir()).unwrap_or(false) {
+            let child = if relative.is_empty() { file_name } else { format!("{}/{}", relative, file_name) };
+            scan_directory(&path, &child, dirs, ancestors)?;
+            continue;
+        }
+
+        // files in the theme directory itself are not icons
+        if relative.is_empty() {
+            continue;
+        }
+
+        let flags = match path.extension().and_then(|x| x.to_str()) {
+            Some("png") => ImageTypeFlags::PNG,
+            Some("svg") => ImageTypeFlags::SVG,
+            Some("xpm") => ImageTypeFlags::XPM,
+            Some("icon") => ImageTypeFlags::ICON_FILE,
+            _ => continue,
+        };
+
+        if let Some(name) = path.file_stem().map(|x| x.to_string_lossy().into_owned()) {
+            *dirs.entry(relative.to_string()).or_default().entry(name).or_default() |= flags;
+        }
+    }
+
+    ancestors.pop();
+
+    Ok(())
+}
+
+///
+/// Big endian writer which keeps every field 4 bytes aligned.
+///
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn offset(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn card16(&mut self, v: u16) {
+        self.data.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn card32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn cstring(&mut self, s: &str) {
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(b'\0');
+
+        while self.data.len() % 4 != 0 {
+            self.data.push(b'\0');
+        }
+    }
+
+    fn patch(&mut self, offset: usize, v: u32) {
+        self.data[offset..offset + 4].copy_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn gtk_hash(name: &str) -> usize {
+    icon_name_hash(name) as usize
+}
+
+///
+/// Pick a prime bucket count keeping load factor around 2/3.
+///
+fn bucket_count(n_icons: usize) -> usize {
+    next_prime(n_icons + n_icons / 2)
+}
+
+///
+/// Pick the smallest prime bucket count keeping load factor below 0.7.
+///
+fn shrunk_bucket_count(n_icons: usize) -> usize {
+    next_prime(n_icons * 10 / 7 + 1)
+}
+
+///
+/// Smallest prime not less than `n`.
+///
+fn next_prime(n: usize) -> usize {
+    let is_prime = |x: usize| x >= 2 && (2..).take_while(|i| i * i <= x).all(|i| x % i != 0);
+
+    (n..).find(|&x| is_prime(x)).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+
+    use GtkIconCache;
+    use GtkIconCacheBuilder;
+    use GtkIconCacheRef;
+    use IconCache;
+    use ImageTypeFlags;
+    use LoadOptions;
+    use super::{merge, next_prime};
+
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io::Cursor;
+    use std::thread;
+
+    #[test]
+    fn test_build_cache() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_scalable = builder.add_directory("apps/scalable");
+        assert_eq!(builder.add_directory("apps/16"), apps_16);
+
+        for i in 0..50 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG),
+                                      (apps_scalable, ImageTypeFlags::SVG)]);
+
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        assert_eq!(icon_cache.n_buckets(), 79);
+        assert_eq!(icon_cache.list_directories(), vec!["apps/16", "apps/scalable"]);
+        assert_eq!(icon_cache.list_all_icons().len(), 51);
+        assert_eq!(icon_cache.icon_count(), 51);
+
+        for i in 0..50 {
+            let dirs = icon_cache.lookup(format!("icon-{}", i)).unwrap();
+            assert_eq!(dirs, vec!["apps/16"]);
+        }
+
+        let entries = icon_cache.lookup_with_flags("firefox").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].dir, "apps/16");
+        assert_eq!(entries[0].flags, ImageTypeFlags::PNG);
+        assert_eq!(entries[1].dir, "apps/scalable");
+        assert_eq!(entries[1].flags, ImageTypeFlags::SVG);
+    }
+
+    #[test]
+    fn test_build_duplicate_icon() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_48 = builder.add_directory("apps/48");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.add_icon("term", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.add_icon("firefox", &[(apps_48, ImageTypeFlags::PNG), (apps_16, ImageTypeFlags::XPM)]);
+
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        assert_eq!(icon_cache.icon_count(), 2);
+        assert_eq!(icon_cache.lookup("firefox").unwrap(), vec!["apps/16", "apps/48"]);
+
+        let entries = icon_cache.lookup_all_with_flags("firefox").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].flags, ImageTypeFlags::PNG | ImageTypeFlags::XPM);
+        assert_eq!(entries[1].flags, ImageTypeFlags::PNG);
+    }
+
+    #[test]
+    fn test_build_round_trip() {
+        const CHARS: &[char] = &['a', 'z', '0', '-', '_', '.', 'é', '图'];
+        const DIRS: &[&str] = &["apps/16", "apps/48", "apps/scalable", "actions/24", "places/32"];
+
+        // xorshift, so every run checks the same cases
+        let mut seed = 0x2545_f491u32;
+        let mut next = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % n
+        };
+
+        for _ in 0..32 {
+            let mut icons = BTreeMap::new();
+            for _ in 0..next(64) {
+                let name: String = (0..1 + next(12)).map(|_| CHARS[next(CHARS.len())]).collect();
+                let mut dirs: Vec<&str> = (0..1 + next(DIRS.len())).map(|_| DIRS[next(DIRS.len())]).collect();
+                dirs.sort();
+                dirs.dedup();
+                icons.insert(name, dirs);
+            }
+
+            let mut builder = GtkIconCacheBuilder::new();
+            for (name, dirs) in &icons {
+                let dirs: Vec<_> = dirs.iter().map(|x| (builder.add_directory(x), ImageTypeFlags::PNG)).collect();
+                builder.add_icon(name, &dirs);
+            }
+
+            let icon_cache = GtkIconCache::with_bytes(&builder.build()).unwrap();
+            assert_eq!(icon_cache.icon_count(), icons.len());
+            for (name, dirs) in &icons {
+                let mut found = icon_cache.lookup(name).unwrap();
+                found.sort();
+                assert_eq!(&found, dirs, "icon {:?}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_empty_cache() {
+        let icon_cache = GtkIconCache::with_bytes_owned(GtkIconCacheBuilder::new().build()).unwrap();
+
+        assert!(icon_cache.list_all_icons().is_empty());
+        assert!(icon_cache.lookup("firefox").is_none());
+    }
+
+    #[test]
+    fn test_build_shrink_to_fit() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        for i in 0..51 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+
+        let default_size = builder.build().len();
+        builder.shrink_to_fit();
+        let data = builder.build();
+        assert!(data.len() < default_size);
+
+        let icon_cache = GtkIconCache::with_bytes_owned(data).unwrap();
+        assert_eq!(icon_cache.n_buckets(), 73);
+        assert!(icon_cache.hash_load_factor() < 0.7);
+        assert!(icon_cache.contains("icon-50"));
+
+        // icons added later still fit
+        for i in 51..100 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        assert_eq!(icon_cache.n_buckets(), 149);
+        assert!(icon_cache.hash_load_factor() < 0.7);
+        assert!(icon_cache.contains("icon-99"));
+    }
+
+    #[test]
+    fn test_build_sizes_table() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        assert_eq!(icon_cache.n_buckets(), 2);
+
+        // icons added after building are counted by the next build
+        for i in 0..99 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+        builder.add_icon("term", &[(apps_16, ImageTypeFlags::PNG)]);
+
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        assert_eq!(icon_cache.n_buckets(), 151);
+        assert_eq!(icon_cache.icon_count(), 101);
+        assert_eq!(icon_cache.lookup("firefox").unwrap(), vec!["apps/16"]);
+        assert_eq!(icon_cache.lookup("icon-98").unwrap(), vec!["apps/16"]);
+        assert_eq!(icon_cache.lookup("term").unwrap(), vec!["apps/16"]);
+    }
+
+    #[test]
+    fn test_build_with_hash_fn() {
+        let mut builder = GtkIconCacheBuilder::with_hash_fn(|_| 0);
+        let apps_16 = builder.add_directory("apps/16");
+        for i in 0..10 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        let stats = icon_cache.stats();
+
+        assert_eq!(stats.n_icons, 10);
+        assert_eq!(stats.max_chain_length, 10);
+        assert_eq!(stats.empty_buckets, stats.n_buckets - 1);
+        assert_eq!(icon_cache.collision_count(), 9);
+    }
+
+    #[test]
+    fn test_build_with_hash_fn_round_trip() {
+        let mut builder = GtkIconCacheBuilder::with_hash_fn(|x| x.len());
+        let apps_16 = builder.add_directory("apps/16");
+        for name in &["firefox", "term", "caf\u{e9}"] {
+            builder.add_icon(name, &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+        let data = builder.build();
+
+        // buckets of the GTK hash are empty
+        let icon_cache = GtkIconCache::with_bytes(&data).unwrap();
+        assert!(icon_cache.lookup("firefox").is_none());
+
+        let opts = LoadOptions::new().hash_fn(|x| x.len());
+        let icon_cache = GtkIconCache::with_bytes_and_options(&data, &opts).unwrap();
+        let cache_ref = GtkIconCacheRef::with_bytes_and_options(&data, &opts).unwrap();
+        for name in &["firefox", "term", "caf\u{e9}"] {
+            assert_eq!(icon_cache.lookup(name).unwrap(), vec!["apps/16"]);
+            assert_eq!(cache_ref.lookup(name).unwrap(), vec!["apps/16"]);
+        }
+        assert!(icon_cache.lookup("not-exist").is_none());
+    }
+
+    #[test]
+    fn test_rebuild_from_directory() {
+        let theme_dir = ::std::env::temp_dir().join(format!("gtk-icon-cache-rebuild-{}", ::std::process::id()));
+        for dir in &["apps/16", "apps/scalable", "empty"] {
+            fs::create_dir_all(theme_dir.join(dir)).unwrap();
+        }
+        for file in &["index.theme", "apps/16/firefox.png", "apps/16/firefox.xpm",
+                      "apps/16/term.png", "apps/16/readme.txt", "apps/scalable/firefox.svg"] {
+            fs::write(theme_dir.join(file), b"").unwrap();
+        }
+
+        let icon_cache = GtkIconCacheBuilder::rebuild_from_directory(&theme_dir);
+        fs::remove_dir_all(&theme_dir).unwrap();
+        let icon_cache = icon_cache.unwrap();
+
+        let mut icons = icon_cache.list_all_icons();
+        icons.sort();
+        assert_eq!(icons, vec!["firefox", "term"]);
+        assert_eq!(icon_cache.list_directories(), vec!["apps/16", "apps/scalable"]);
+
+        let entries = icon_cache.lookup_with_flags("firefox").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].dir, "apps/16");
+        assert_eq!(entries[0].flags, ImageTypeFlags::PNG | ImageTypeFlags::XPM);
+        assert_eq!(entries[1].dir, "apps/scalable");
+        assert_eq!(entries[1].flags, ImageTypeFlags::SVG);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rebuild_from_directory_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let theme_dir = ::std::env::temp_dir().join(format!("gtk-icon-cache-symlinks-{}", ::std::process::id()));
+        fs::create_dir_all(theme_dir.join("48x48/apps")).unwrap();
+        fs::write(theme_dir.join("48x48/apps/firefox.png"), b"").unwrap();
+        symlink("48x48", theme_dir.join("48x48@2x")).unwrap();
+        // loops back to the theme directory
+        symlink("..", theme_dir.join("48x48/apps/loop")).unwrap();
+        symlink("not-exist", theme_dir.join("48x48/apps/broken.png")).unwrap();
+
+        let icon_cache = GtkIconCacheBuilder::rebuild_from_directory(&theme_dir);
+        fs::remove_dir_all(&theme_dir).unwrap();
+        let icon_cache = icon_cache.unwrap();
+
+        assert_eq!(icon_cache.lookup("firefox").unwrap(), vec!["48x48/apps", "48x48@2x/apps"]);
+        assert!(icon_cache.contains("broken"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.add_icon("term", &[(apps_16, ImageTypeFlags::PNG)]);
+        let a = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_scalable = builder.add_directory("apps/scalable");
+        builder.add_icon("firefox", &[(apps_scalable, ImageTypeFlags::SVG)]);
+        builder.add_icon("editor", &[(apps_scalable, ImageTypeFlags::SVG)]);
+        let b = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        let merged = merge(&a, &b).unwrap();
+        let mut icons = merged.list_all_icons();
+        icons.sort();
+        assert_eq!(icons, vec!["editor", "firefox", "term"]);
+        assert_eq!(merged.lookup("firefox").unwrap(), vec!["apps/16"]);
+        assert_eq!(merged.lookup("editor").unwrap(), vec!["apps/scalable"]);
+        assert_eq!(merged.lookup_with_flags("editor").unwrap()[0].flags, ImageTypeFlags::SVG);
+    }
+
+    #[test]
+    fn test_merge_non_utf8() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_bad = builder.add_directory("apps/bad");
+        builder.add_icon("cafe", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.add_icon("term", &[(apps_bad, ImageTypeFlags::PNG)]);
+        builder.add_icon("editor", &[(apps_16, ImageTypeFlags::PNG), (apps_bad, ImageTypeFlags::SVG)]);
+
+        let mut data = builder.build();
+        let pos = data.windows(5).position(|x| x == b"cafe\0").unwrap();
+        data[pos + 3] = 0xe9;
+        let pos = data.windows(8).position(|x| x == b"apps/bad").unwrap();
+        data[pos + 5] = 0xff;
+        let a = GtkIconCache::with_bytes_owned(data).unwrap();
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_scalable = builder.add_directory("apps/scalable");
+        builder.add_icon("term", &[(apps_scalable, ImageTypeFlags::SVG)]);
+        let b = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        // every icon keeps a directory
+        let merged = merge(&a, &b).unwrap();
+        let icons: BTreeMap<_, _> = merged.to_hashmap().into_iter().collect();
+        assert_eq!(icons.into_iter().collect::<Vec<_>>(), vec![
+            ("editor".to_string(), vec!["apps/16".to_string()]),
+            ("term".to_string(), vec!["apps/scalable".to_string()]),
+        ]);
+        assert!(merged.validate().is_ok());
+    }
+
+    #[test]
+    fn test_build_from_iter() {
+        let icon_cache = GtkIconCache::with_file_path("test/caches/test1.cache").unwrap();
+
+        let mut icons = icon_cache.to_hashmap();
+        icons.remove("test");
+        icons.insert("firefox".to_string(), vec!["apps/64".to_string()]);
+
+        let builder: GtkIconCacheBuilder = icons.clone().into_iter().collect();
+        let rebuilt = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        assert_eq!(rebuilt.to_hashmap(), icons);
+        assert!(!rebuilt.contains("test"));
+        assert_eq!(rebuilt.lookup("firefox").unwrap(), vec!["apps/64"]);
+        assert_eq!(rebuilt.lookup_flags("firefox").unwrap(), vec![("apps/64", ImageTypeFlags::PNG.bits())]);
+    }
+
+    #[test]
+    fn test_write_to() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+
+        let mut cursor = Cursor::new(Vec::new());
+        builder.write_to(&mut cursor).unwrap();
+        assert_eq!(cursor.into_inner(), builder.build());
+    }
+
+    #[test]
+    fn test_write_to_file() {
+        let dir = ::std::env::temp_dir().join(format!("gtk-icon-cache-write-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("icon-theme.cache");
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.write_to_file(&path).unwrap();
+
+        let icon_cache = GtkIconCache::with_file_path(&path).unwrap();
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().map(|x| x.unwrap().file_name()).collect();
+        let not_dir = builder.write_to_file(dir.join("not-exist/icon-theme.cache"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(icon_cache.contains("firefox"));
+        assert_eq!(files, vec!["icon-theme.cache"]);
+        assert!(not_dir.is_err());
+    }
+
+    #[test]
+    fn test_write_to_file_concurrent() {
+        let dir = ::std::env::temp_dir().join(format!("gtk-icon-cache-write-concurrent-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("icon-theme.cache");
+
+        let threads: Vec<_> = (0..8).map(|i| {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut builder = GtkIconCacheBuilder::new();
+                let apps_16 = builder.add_directory("apps/16");
+                for j in 0..100 * i {
+                    builder.add_icon(&format!("icon-{}", j), &[(apps_16, ImageTypeFlags::PNG)]);
+                }
+                builder.write_to_file(&path)
+            })
+        }).collect();
+
+        let results: Vec<_> = threads.into_iter().map(|x| x.join().unwrap()).collect();
+        let icon_cache = GtkIconCache::with_file_path(&path);
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().map(|x| x.unwrap().file_name()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(results.iter().all(|x| x.is_ok()));
+        assert!(icon_cache.unwrap().validate().is_ok());
+        assert_eq!(files, vec!["icon-theme.cache"]);
+    }
+
+    #[test]
+    fn test_next_prime() {
+        assert_eq!(next_prime(0), 2);
+        assert_eq!(next_prime(2), 2);
+        assert_eq!(next_prime(8), 11);
+        assert_eq!(next_prime(75), 79);
+    }
+}
@@ -0,0 +1,82 @@
+use GtkIconCache;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+///
+/// Icons which differ between two caches, see `diff`. Names are sorted.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CacheDiff {
+    /// Icons only in the new cache.
+    pub added: Vec<String>,
+    /// Icons only in the old cache.
+    pub removed: Vec<String>,
+    /// Icons in both caches, found in different directories.
+    pub changed: Vec<String>,
+}
+
+///
+/// Compare icons of two caches.
+///
+/// * `old` - cache before the change.
+/// * `new` - cache after the change.
+///
+pub fn diff(old: &GtkIconCache, new: &GtkIconCache) -> CacheDiff {
+    let old_icons = icon_dirs(old);
+    let new_icons = icon_dirs(new);
+
+    let mut r = CacheDiff::default();
+
+    for (name, dirs) in &new_icons {
+        match old_icons.get(name) {
+            None => r.added.push(name.clone()),
+            Some(old_dirs) if old_dirs != dirs => r.changed.push(name.clone()),
+            _ => {},
+        }
+    }
+
+    r.removed = old_icons.keys()
+        .filter(|x| !new_icons.contains_key(*x))
+        .cloned()
+        .collect();
+
+    r
+}
+
+fn icon_dirs(cache: &GtkIconCache) -> BTreeMap<String, BTreeSet<String>> {
+    cache.iter()
+        .map(|(name, dirs)| (name, dirs.into_iter().collect()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use GtkIconCache;
+    use GtkIconCacheBuilder;
+    use ImageTypeFlags;
+    use super::diff;
+
+    fn build(icons: &[(&str, &str)]) -> GtkIconCache {
+        let mut builder = GtkIconCacheBuilder::new();
+        for &(name, dir) in icons {
+            let dir_index = builder.add_directory(dir);
+            builder.add_icon(name, &[(dir_index, ImageTypeFlags::PNG)]);
+        }
+
+        GtkIconCache::with_bytes_owned(builder.build()).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = build(&[("firefox", "apps/16"), ("term", "apps/16"), ("editor", "apps/16")]);
+        let new = build(&[("firefox", "apps/16"), ("term", "apps/32"), ("browser", "apps/16")]);
+
+        let r = diff(&old, &new);
+        assert_eq!(r.added, vec!["browser"]);
+        assert_eq!(r.removed, vec!["editor"]);
+        assert_eq!(r.changed, vec!["term"]);
+
+        assert_eq!(diff(&old, &old), Default::default());
+    }
+}
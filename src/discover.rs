@@ -0,0 +1,88 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_DATA_DIRS: &str = "/usr/local/share:/usr/share";
+
+///
+/// Find cache files of installed icon themes, as `(theme_name, cache_path)`.
+///
+/// Themes are searched in `<data_dir>/icons/*/icon-theme.cache` for each
+/// directory in `$XDG_DATA_DIRS`, in order. Directories which can not be
+/// read are skipped.
+///
+pub fn discover_system_caches() -> Vec<(String, PathBuf)> {
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| DEFAULT_DATA_DIRS.to_string());
+
+    let data_dirs: Vec<_> = env::split_paths(&data_dirs).collect();
+
+    discover_caches_in(&data_dirs)
+}
+
+fn discover_caches_in(data_dirs: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut r = Vec::new();
+
+    for data_dir in data_dirs {
+        let mut found = caches_in_icons_dir(&data_dir.join("icons"));
+        found.sort();
+        r.extend(found);
+    }
+
+    r
+}
+
+fn caches_in_icons_dir(icons_dir: &Path) -> Vec<(String, PathBuf)> {
+    let entries = match fs::read_dir(icons_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("skip {}: {}", icons_dir.display(), e);
+            return Vec::new();
+        },
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let cache_path = entry.path().join("icon-theme.cache");
+            if !cache_path.is_file() {
+                return None;
+            }
+
+            let theme_name = entry.file_name().to_string_lossy().into_owned();
+            Some((theme_name, cache_path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::discover_caches_in;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_discover_caches() {
+        let root = env::temp_dir().join(format!("gtk-icon-cache-discover-{}", process::id()));
+        let data_dir = root.join("share");
+
+        for theme in &["hicolor", "Adwaita", "no-cache"] {
+            fs::create_dir_all(data_dir.join("icons").join(theme)).unwrap();
+        }
+        fs::write(data_dir.join("icons/hicolor/icon-theme.cache"), b"").unwrap();
+        fs::write(data_dir.join("icons/Adwaita/icon-theme.cache"), b"").unwrap();
+
+        let found = discover_caches_in(&[root.join("not-exist"), data_dir.clone()]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(found, vec![
+            ("Adwaita".to_string(), data_dir.join("icons/Adwaita/icon-theme.cache")),
+            ("hicolor".to_string(), data_dir.join("icons/hicolor/icon-theme.cache")),
+        ]);
+    }
+}
@@ -0,0 +1,83 @@
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+///
+/// Errors that can occur when loading a cache.
+///
+#[derive(Debug)]
+pub enum CacheError {
+    /// Failed to open or map the cache file.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// The cache was written in an unsupported format version.
+    InvalidVersion { major: u16, minor: u16 },
+    /// The cache ends before a field which should be at `offset`.
+    TruncatedData { offset: usize },
+    /// An offset in the cache points outside of it.
+    InvalidOffset { offset: usize },
+    /// The string at `offset` is not valid UTF-8.
+    InvalidUtf8 { offset: usize },
+    /// An image list refers to a directory which does not exist.
+    InvalidDirectoryIndex { index: usize },
+    /// The cache was not loaded from a file, so it can not be reloaded.
+    NoSourcePath,
+    /// The hash table does not fit in the cache, or its chains form a cycle.
+    CorruptHashTable,
+    /// The hash table at `hash_offset` and the directory list at
+    /// `directory_list_offset` overlap.
+    OverlappingTables { hash_offset: usize, directory_list_offset: usize },
+    /// The chain of `bucket` has `length` entries, more than allowed.
+    ChainTooLong { bucket: usize, length: usize },
+    /// The theme directory exists but contains no `icon-theme.cache`.
+    #[cfg(feature = "std")]
+    MissingThemeCache { theme_dir: PathBuf },
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "std")]
+            CacheError::Io(ref e) => write!(f, "io error: {}", e),
+            CacheError::InvalidVersion { major, minor } =>
+                write!(f, "unsupported cache version {}.{}", major, minor),
+            CacheError::TruncatedData { offset } =>
+                write!(f, "cache data truncated at offset {}", offset),
+            CacheError::InvalidOffset { offset } =>
+                write!(f, "offset {} is out of bounds", offset),
+            CacheError::InvalidUtf8 { offset } =>
+                write!(f, "string at offset {} is not valid utf-8", offset),
+            CacheError::InvalidDirectoryIndex { index } =>
+                write!(f, "directory index {} does not exist", index),
+            CacheError::NoSourcePath => write!(f, "cache has no source path"),
+            CacheError::CorruptHashTable => write!(f, "corrupt hash table"),
+            CacheError::OverlappingTables { hash_offset, directory_list_offset } =>
+                write!(f, "hash table at offset {} overlaps directory list at offset {}", hash_offset, directory_list_offset),
+            CacheError::ChainTooLong { bucket, length } =>
+                write!(f, "bucket {} has a chain of {} entries", bucket, length),
+            #[cfg(feature = "std")]
+            CacheError::MissingThemeCache { ref theme_dir } =>
+                write!(f, "no icon-theme.cache in {}", theme_dir.display()),
+        }
+    }
+}
+
+impl Error for CacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            #[cfg(feature = "std")]
+            CacheError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for CacheError {
+    fn from(e: io::Error) -> Self {
+        CacheError::Io(e)
+    }
+}
@@ -0,0 +1,83 @@
+use core::ops::{BitAnd, BitOr, BitOrAssign};
+
+/// A `.xpm` file is present, raw bit of `ImageTypeFlags::XPM`.
+pub const ICON_TYPE_XPM: u16 = 0x1;
+/// A `.svg` file is present, raw bit of `ImageTypeFlags::SVG`.
+pub const ICON_TYPE_SVG: u16 = 0x2;
+/// A `.png` file is present, raw bit of `ImageTypeFlags::PNG`.
+pub const ICON_TYPE_PNG: u16 = 0x4;
+/// A `.icon` file is present, raw bit of `ImageTypeFlags::ICON_FILE`.
+pub const ICON_TYPE_ICON_FILE: u16 = 0x8;
+
+///
+/// Image types available for an icon in a directory.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ImageTypeFlags {
+    bits: u16,
+}
+
+impl ImageTypeFlags {
+    /// A `.xpm` file is present.
+    pub const XPM: ImageTypeFlags = ImageTypeFlags { bits: ICON_TYPE_XPM };
+    /// A `.svg` file is present.
+    pub const SVG: ImageTypeFlags = ImageTypeFlags { bits: ICON_TYPE_SVG };
+    /// A `.png` file is present.
+    pub const PNG: ImageTypeFlags = ImageTypeFlags { bits: ICON_TYPE_PNG };
+    /// A `.icon` file is present.
+    pub const ICON_FILE: ImageTypeFlags = ImageTypeFlags { bits: ICON_TYPE_ICON_FILE };
+
+    ///
+    /// No flags set.
+    ///
+    pub fn empty() -> Self {
+        ImageTypeFlags { bits: 0 }
+    }
+
+    ///
+    /// Create from raw bits, unknown bits are dropped.
+    ///
+    pub fn from_bits_truncate(bits: u16) -> Self {
+        ImageTypeFlags { bits: bits & 0xf }
+    }
+
+    ///
+    /// Raw bits of the flags.
+    ///
+    pub fn bits(&self) -> u16 {
+        self.bits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    ///
+    /// Check if all flags in `other` are set.
+    ///
+    pub fn contains(&self, other: ImageTypeFlags) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl BitOr for ImageTypeFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        ImageTypeFlags { bits: self.bits | rhs.bits }
+    }
+}
+
+impl BitOrAssign for ImageTypeFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.bits |= rhs.bits;
+    }
+}
+
+impl BitAnd for ImageTypeFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        ImageTypeFlags { bits: self.bits & rhs.bits }
+    }
+}
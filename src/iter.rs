@@ -0,0 +1,51 @@
+use GtkIconCache;
+
+///
+/// Iterator over `(icon_name, dirs)` of all icons in a cache, created by
+/// `GtkIconCache::iter`.
+///
+pub struct Iter<'a> {
+    cache: &'a GtkIconCache,
+    index: usize,
+}
+
+impl<'a> Iter<'a> {
+    pub(crate) fn new(cache: &'a GtkIconCache) -> Self {
+        Iter {
+            cache,
+            index: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (String, Vec<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&(name_offset, list_offset)) = self.cache.entries.get(self.index) {
+            self.index += 1;
+
+            let name = self.cache.read_cstring_from(name_offset);
+            let dirs = self.cache.read_dirs(list_offset);
+
+            if let (Some(name), Some(dirs)) = (name, dirs) {
+                return Some((name, dirs.into_iter().map(|x| x.to_string()).collect()));
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cache.entries.len() - self.index))
+    }
+}
+
+impl<'a> IntoIterator for &'a GtkIconCache {
+    type Item = (String, Vec<String>);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
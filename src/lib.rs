@@ -2,6 +2,7 @@
 //! This crate provide a reader for gtk-icon-cache file.
 //!
 //! ```
+//! # #[cfg(feature = "std")] {
 //! use gtk_icon_cache::*;
 //!
 //! let path = "test/caches/icon-theme.cache";
@@ -11,219 +12,2846 @@
 //! let dirs = icon_cache.lookup("firefox").unwrap();
 //!
 //! // icon should be found in apps/64
-//! assert!(dirs.contains(&&"apps/64".to_string()));
+//! assert!(dirs.contains(&"apps/64"));
+//! # }
 //! ```
 //!
 //! _See_:
 //! - [GTK icon-cache specific](https://github.com/GNOME/gtk/blob/master/docs/iconcache.txt)
 //! - [Qt icon loader](https://codereview.qt-project.org/#/c/125379/9/src/gui/image/qiconloader.cpp)
 //!
+//! Without the default `std` feature the crate only needs `core` and `alloc`, only
+//! `GtkIconCacheRef` is available to parse cache data.
+//!
+//! Diagnostics are logged with `log`, or emitted as `tracing` events and
+//! spans with the `tracing` feature.
+//!
+//! With the `serde` feature `GtkIconCache` implements `Serialize` and
+//! `Deserialize`, as its decoded directories and icons.
+//!
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
-extern crate memmap;
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "std")]
+extern crate memmap2;
+#[cfg(not(all(feature = "tracing", feature = "std")))]
 #[macro_use]
 extern crate log;
+#[cfg(all(feature = "serde", feature = "std"))]
+extern crate serde;
+#[cfg(all(feature = "tracing", feature = "std"))]
+#[macro_use]
+extern crate tracing;
+#[cfg(all(feature = "tokio", feature = "std"))]
+extern crate tokio;
+
+#[cfg(all(feature = "tokio", feature = "std"))]
+mod async_load;
+mod borrowed;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod diff;
+#[cfg(feature = "std")]
+mod discover;
+mod error;
+mod flags;
+#[cfg(feature = "std")]
+mod iter;
+mod options;
+mod parse;
+#[cfg(all(feature = "serde", feature = "std"))]
+mod serialize;
+#[cfg(feature = "std")]
+mod set;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod storage;
+mod traits;
+
+#[cfg(all(feature = "tokio", feature = "std"))]
+pub use async_load::LoadFuture;
+pub use borrowed::GtkIconCacheRef;
+#[cfg(feature = "std")]
+pub use builder::{GtkIconCacheBuilder, merge};
+#[cfg(feature = "std")]
+pub use diff::{CacheDiff, diff};
+#[cfg(feature = "std")]
+pub use discover::discover_system_caches;
+pub use error::CacheError;
+pub use flags::{ImageTypeFlags, ICON_TYPE_XPM, ICON_TYPE_SVG, ICON_TYPE_PNG, ICON_TYPE_ICON_FILE};
+#[cfg(feature = "std")]
+pub use iter::Iter;
+pub use options::{ByteOrder, LoadOptions, LookupOptions, MmapAdvice};
+#[cfg(feature = "std")]
+pub use set::{GtkIconCacheSet, lookup_multiple_themes};
+#[cfg(feature = "std")]
+pub use stats::CacheStats;
+pub use traits::IconCache;
+
+#[cfg(feature = "std")]
+use storage::BackingStorage;
+#[cfg(feature = "std")]
+use parse::{ChainLengthCheck, Header, Reader};
 
-use memmap::Mmap;
+#[cfg(feature = "std")]
+use memmap2::Mmap;
+#[cfg(all(feature = "tracing", feature = "std"))]
+use tracing::instrument;
 
-use std::io::{ErrorKind, Result, Error};
-use std::num::Wrapping;
+use core::num::Wrapping;
+#[cfg(feature = "std")]
+use std::cmp::Reverse;
+#[cfg(feature = "std")]
+use std::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
 use std::fs::File;
-use std::path::Path;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::collections::hash_map::Entry;
+#[cfg(feature = "std")]
 use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+
+/// Offset of an empty bucket or the end of a chain.
+const EMPTY_OFFSET: usize = 0xffff_ffff;
+
+/// Chain length after which lookups start checking for cycles.
+const CHAIN_CHECK_THRESHOLD: usize = 16;
+
+/// Longest string scanned when reading names from the cache.
+const MAX_NAME_LEN: usize = 4096;
+
+///
+/// A directory an icon is found in, with the image types present there.
+///
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IconDirEntry<'a> {
+    pub dir: &'a str,
+    pub flags: ImageTypeFlags,
+}
 
 ///
 /// GtkIconCache
 ///
+/// The cache is never modified after loading, so it is `Send + Sync` and can
+/// be shared between threads, cloning is cheap as the data is shared.
+///
+#[cfg(feature = "std")]
 #[derive(Debug, Clone)]
 pub struct GtkIconCache {
+    major_version: u16,
+    minor_version: u16,
+
     hash_offset: usize,
     directory_list_offset: usize,
 
     n_buckets: usize,
+    n_directorys: usize,
+    preloaded: HashMap<String, usize>,
+    collision_count: usize,
+    load_options: LoadOptions,
+    image_lists: HashMap<usize, Vec<(usize, u16)>>,
+    /// `(name_offset, list_offset)` of all icons, in hash table order.
+    entries: Vec<(usize, usize)>,
 
-    dir_names: HashMap<usize, String>,
-    file_mmap: Arc<Mmap>,
+    /// Name offset of each directory, by index.
+    dir_offsets: Vec<usize>,
+    /// Lossy converted names of directories which are not valid UTF-8, by
+    /// name offset.
+    lossy_dir_names: HashMap<usize, String>,
+    storage: BackingStorage,
+    source_path: Option<PathBuf>,
+    last_modified: Option<SystemTime>,
 }
 
+#[cfg(feature = "std")]
 impl GtkIconCache {
     ///
     /// Create with a cache file.
     ///
     /// * `path` - Cache file path.
     ///
-    pub fn with_file_path<T: AsRef<Path>>(path: T) -> Result<Self> {
+    pub fn with_file_path<T: AsRef<Path>>(path: T) -> Result<Self, CacheError> {
+        Self::with_file_path_and_options(path, &LoadOptions::new())
+    }
+
+    ///
+    /// Create with a cache file, with `opts` changing how it is loaded.
+    ///
+    /// * `path` - Cache file path.
+    /// * `opts` - load options.
+    ///
+    #[cfg_attr(feature = "tracing", instrument(level = "debug", skip_all, fields(path = %path.as_ref().display())))]
+    pub fn with_file_path_and_options<T: AsRef<Path>>(path: T, opts: &LoadOptions) -> Result<Self, CacheError> {
         // read data
-        let f = File::open(&path.as_ref())?;
-        let _last_modified = f.metadata().and_then(|x| x.modified()).ok();
-        let mmap = unsafe { Mmap::map(&f)? };
+        let f = File::open(path.as_ref())?;
 
-        let r = Self {
-            hash_offset: 0,
-            directory_list_offset: 0,
+        let mut r = Self::with_file_and_options(f, opts)?;
+        r.source_path = Some(path.as_ref().to_path_buf());
 
-            n_buckets: 0,
+        Ok(r)
+    }
+
+    ///
+    /// Create with a cache file read without blocking the tokio runtime.
+    ///
+    /// The file is read into memory instead of being mapped, parsing happens
+    /// on the task polling the returned future.
+    ///
+    /// * `path` - Cache file path.
+    ///
+    #[cfg(feature = "tokio")]
+    pub fn with_file_path_async<T: AsRef<Path>>(path: T) -> LoadFuture {
+        LoadFuture::new(path.as_ref().to_path_buf())
+    }
+
+    ///
+    /// Create with the `icon-theme.cache` file of a theme directory.
+    ///
+    /// * `theme_dir` - Theme directory, e.g. `/usr/share/icons/hicolor`.
+    ///
+    pub fn from_theme_dir<T: AsRef<Path>>(theme_dir: T) -> Result<Self, CacheError> {
+        let theme_dir = theme_dir.as_ref();
+        let path = theme_dir.join("icon-theme.cache");
+
+        if theme_dir.is_dir() && !path.exists() {
+            return Err(CacheError::MissingThemeCache { theme_dir: theme_dir.to_path_buf() });
+        }
+
+        Self::with_file_path(path)
+    }
+
+    ///
+    /// Create with an opened cache file, the cache can not be reloaded as
+    /// its path is unknown.
+    ///
+    /// * `file` - Cache file.
+    ///
+    pub fn with_file(file: File) -> Result<Self, CacheError> {
+        Self::with_file_and_options(file, &LoadOptions::new())
+    }
+
+    fn with_file_and_options(file: File, opts: &LoadOptions) -> Result<Self, CacheError> {
+        let last_modified = file.metadata().and_then(|x| x.modified()).ok();
+        let mmap = unsafe { Mmap::map(&file)? };
+        #[cfg(unix)]
+        mmap.advise(opts.mmap_advice.into())?;
 
-            dir_names: HashMap::new(),
-            file_mmap: Arc::new(mmap),
+        let mut r = Self::with_storage_and_options(BackingStorage::Mmap(Arc::new(mmap)), opts)?;
+        r.last_modified = last_modified;
+
+        Ok(r)
+    }
+
+    ///
+    /// Load the cache file again, for cache files updated after being opened.
+    ///
+    /// The cache is left unchanged if reloading fails. Icon names are read
+    /// into memory again if `preload_all` was called before.
+    ///
+    pub fn reload(&mut self) -> Result<(), CacheError> {
+        let mut cache = match self.source_path {
+            Some(ref path) => Self::with_file_path_and_options(path, &self.load_options)?,
+            None => return Err(CacheError::NoSourcePath),
         };
 
-        match r.load_cache() {
-            Some(cache) => Ok(cache),
-            _ => Err(Error::new(ErrorKind::Other, "cache load failed.")),
+        if !self.preloaded.is_empty() {
+            cache.preload_all();
         }
+
+        *self = cache;
+
+        Ok(())
     }
 
-    fn load_cache(mut self) -> Option<Self> {
+    ///
+    /// Read all icon names into memory, so later lookups are served from a
+    /// map instead of walking the hash table.
+    ///
+    pub fn preload_all(&mut self) {
+        let mut preloaded = HashMap::new();
 
-        let major_version = self.read_card16_from(0)?;
-        let minor_version = self.read_card16_from(2)?;
+        self.walk_entries(|entry_offset| {
+            let name = self.read_card32_from(entry_offset + 4).and_then(|x| self.read_cstring_bytes_from(x));
+            let list_offset = self.read_card32_from(entry_offset + 8);
+
+            if let (Some(name), Some(list_offset)) = (name, list_offset) {
+                if let Ok(name) = ::std::str::from_utf8(name) {
+                    preloaded.entry(name.to_string()).or_insert(list_offset);
+                }
+            }
+        });
 
-        self.hash_offset = self.read_card32_from(4)?;
-        self.directory_list_offset = self.read_card32_from(8)?;
-        self.n_buckets = self.read_card32_from(self.hash_offset)?;
+        self.preloaded = preloaded;
+    }
 
-        if major_version != 1usize && minor_version != 0usize {
-            return None;
+    ///
+    /// Create with cache data already in memory, the data is copied.
+    ///
+    /// * `data` - Cache file content.
+    ///
+    pub fn with_bytes(data: &[u8]) -> Result<Self, CacheError> {
+        Self::with_bytes_owned(data.to_vec())
+    }
+
+    ///
+    /// Create with cache data already in memory, with `opts` changing how it
+    /// is loaded, the data is copied.
+    ///
+    /// * `data` - Cache file content.
+    /// * `opts` - load options.
+    ///
+    pub fn with_bytes_and_options(data: &[u8], opts: &LoadOptions) -> Result<Self, CacheError> {
+        Self::with_storage_and_options(BackingStorage::Bytes(Arc::new(data.to_vec())), opts)
+    }
+
+    ///
+    /// Create with cache data already in memory, taking ownership of it.
+    ///
+    /// * `data` - Cache file content.
+    ///
+    pub fn with_bytes_owned(data: Vec<u8>) -> Result<Self, CacheError> {
+        Self::with_storage(BackingStorage::Bytes(Arc::new(data)))
+    }
+
+    ///
+    /// Create with cache data read from `reader` until its end, e.g. a pipe
+    /// or socket.
+    ///
+    /// * `reader` - source of cache file content.
+    ///
+    pub fn with_reader<R: Read>(mut reader: R) -> Result<Self, CacheError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        Self::with_bytes_owned(data)
+    }
+
+    ///
+    /// Create with cache data which lives forever, e.g. from `include_bytes!`,
+    /// the data is not copied.
+    ///
+    /// * `data` - Cache file content.
+    ///
+    pub fn with_static_bytes(data: &'static [u8]) -> Result<Self, CacheError> {
+        Self::with_storage(BackingStorage::Static(data))
+    }
+
+    ///
+    /// Create with cache data without checking its header or chain lengths,
+    /// for data known to be a valid cache, e.g. built by `GtkIconCacheBuilder`
+    /// and embedded at compile time.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be a well-formed cache with format version 1.0, as
+    /// `with_static_bytes` would accept it. Lookups on other data return
+    /// unspecified results.
+    ///
+    /// * `data` - Cache file content.
+    ///
+    #[doc(hidden)]
+    pub unsafe fn from_slice_unchecked(data: &'static [u8]) -> Self {
+        let mut r = Self::unloaded(BackingStorage::Static(data));
+
+        r.major_version = 1;
+        r.minor_version = 0;
+        r.hash_offset = r.read_card32_from(4).unwrap_or(0);
+        r.directory_list_offset = r.read_card32_from(8).unwrap_or(0);
+        r.n_buckets = r.read_card32_from(r.hash_offset).unwrap_or(0);
+        r.n_directorys = r.read_card32_from(r.directory_list_offset).unwrap_or(0);
+
+        r.dir_offsets = (0..r.n_directorys)
+            .filter_map(|i| r.read_card32_from(r.directory_list_offset + 4 + 4 * i))
+            .collect();
+
+        // only fails for chains longer than `max_chain_length`, which is unset
+        let _ = r.finish_load();
+
+        r
+    }
+
+    fn with_storage(storage: BackingStorage) -> Result<Self, CacheError> {
+        Self::with_storage_and_options(storage, &LoadOptions::new())
+    }
+
+    fn with_storage_and_options(storage: BackingStorage, opts: &LoadOptions) -> Result<Self, CacheError> {
+        let mut r = Self::unloaded(storage);
+        r.load_options = *opts;
+
+        r.load_cache()
+    }
+
+    fn unloaded(storage: BackingStorage) -> Self {
+        Self {
+            major_version: 0,
+            minor_version: 0,
+
+            hash_offset: 0,
+            directory_list_offset: 0,
+
+            n_buckets: 0,
+            n_directorys: 0,
+            preloaded: HashMap::new(),
+            collision_count: 0,
+            load_options: LoadOptions::new(),
+            image_lists: HashMap::new(),
+            entries: Vec::new(),
+
+            dir_offsets: Vec::new(),
+            lossy_dir_names: HashMap::new(),
+            storage,
+            source_path: None,
+            last_modified: None,
         }
+    }
 
-        let n_directorys = self.read_card32_from(self.directory_list_offset)?;
+    #[cfg_attr(feature = "tracing", instrument(level = "debug", skip_all, fields(size = self.storage.len())))]
+    fn load_cache(mut self) -> Result<Self, CacheError> {
+
+        let header = self.reader().header()?;
+
+        self.major_version = header.major_version;
+        self.minor_version = header.minor_version;
+        self.hash_offset = header.hash_offset;
+        self.directory_list_offset = header.directory_list_offset;
+        self.n_buckets = header.n_buckets;
+        self.n_directorys = header.n_directories;
 
         // dump directories
-        for i in 0..n_directorys {
-            let offset = self.read_card32_from(self.directory_list_offset + 4 + 4 * i)?;
-            if let Some(dir) = self.read_cstring_from(offset as usize) {
-                self.dir_names.insert(offset, dir);
-            }
+        for i in 0..self.n_directorys {
+            let dir_offset = self.directory_list_offset + 4 + 4 * i;
+            let offset = self.read_card32_from(dir_offset).ok_or(CacheError::TruncatedData { offset: dir_offset })?;
+            self.dir_offsets.push(offset);
         }
 
+        self.finish_load()?;
+
         trace!("{:#?}", self);
 
-        Some(self)
+        Ok(self)
     }
 
-    fn read_card16_from(&self, offset: usize) -> Option<usize> {
-        let m = &self.file_mmap;
+    ///
+    /// Decode hash table entries and directory names, once the header and
+    /// directory list are read, checking chain lengths against
+    /// `max_chain_length` of the load options.
+    ///
+    fn finish_load(&mut self) -> Result<(), CacheError> {
+        // decode image lists once, lookups then only walk the hash chain
+        let mut image_lists = HashMap::new();
+        let mut entries = Vec::new();
+        let mut collision_count = 0;
+        // degenerate tables make lookups scan most of the cache
+        let mut check = ChainLengthCheck::new(self.load_options.max_chain_length);
+        self.walk_buckets(|bucket_index, entry_offset| {
+            // every entry after the first one of a chain is a collision
+            if check.visit(bucket_index) {
+                collision_count += 1;
+            }
 
-        if offset < self.file_mmap.len() - 2 {
-            Some((m[offset    ] as usize) << 8 |
-                 (m[offset + 1] as usize))
-        } else {
-            None
+            let name_offset = self.read_card32_from(entry_offset + 4);
+            let list_offset = self.read_card32_from(entry_offset + 8);
+
+            if let (Some(name_offset), Some(list_offset)) = (name_offset, list_offset) {
+                if let (Entry::Vacant(e), Some(images)) = (image_lists.entry(list_offset), self.read_image_list(list_offset)) {
+                    e.insert(images);
+                }
+                entries.push((name_offset, list_offset));
+            }
+        });
+
+        check.finish(&self.reader(), &self.header())?;
+
+        self.image_lists = image_lists;
+        self.entries = entries;
+        self.collision_count = collision_count;
+
+        // keep directories which are not valid UTF-8 listed and found
+        let mut lossy_dir_names = HashMap::new();
+        for &offset in &self.dir_offsets {
+            if let Some(bytes) = self.read_cstring_bytes_from(offset) {
+                if ::std::str::from_utf8(bytes).is_err() {
+                    lossy_dir_names.insert(offset, String::from_utf8_lossy(bytes).into_owned());
+                }
+            }
         }
+        self.lossy_dir_names = lossy_dir_names;
+
+        Ok(())
     }
 
-    fn read_card32_from(&self, offset: usize) -> Option<usize> {
-        let m = &self.file_mmap;
+    ///
+    /// Modification time of the cache file, `None` if not loaded from a file.
+    ///
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        self.last_modified
+    }
 
-        if offset > 0 && offset < self.file_mmap.len() - 4 {
-            Some((m[offset    ] as usize) << 24 |
-                 (m[offset + 1] as usize) << 16 |
-                 (m[offset + 2] as usize) <<  8 |
-                 (m[offset + 3] as usize))
-        } else {
-            None
+    ///
+    /// Check if `path` was modified after the cache file, a cache without
+    /// modification time is always stale.
+    ///
+    /// * `path` - usually the theme directory.
+    ///
+    pub fn is_stale_compared_to<T: AsRef<Path>>(&self, path: T) -> Result<bool, CacheError> {
+        let last_modified = match self.last_modified {
+            Some(x) => x,
+            None => return Ok(true),
+        };
+
+        Ok(path.as_ref().metadata()?.modified()? > last_modified)
+    }
+
+    ///
+    /// Check if the theme directory was modified after the cache file, which
+    /// should then be rebuilt. A cache not loaded from a file is always stale.
+    ///
+    /// * `theme_dir` - icon theme directory of the cache.
+    ///
+    pub fn is_stale<T: AsRef<Path>>(&self, theme_dir: T) -> Result<bool, CacheError> {
+        self.is_stale_compared_to(theme_dir)
+    }
+
+    ///
+    /// Format version of the cache, as `(major, minor)`.
+    ///
+    pub fn version(&self) -> (u16, u16) {
+        (self.major_version, self.minor_version)
+    }
+
+    ///
+    /// Number of buckets in the hash table.
+    ///
+    pub fn n_buckets(&self) -> usize {
+        self.n_buckets
+    }
+
+    ///
+    /// Size of the cache data in bytes, e.g. the cache file size.
+    ///
+    pub fn size_bytes(&self) -> usize {
+        self.storage.len()
+    }
+
+    ///
+    /// Same as `size_bytes`.
+    ///
+    pub fn backing_data_len(&self) -> usize {
+        self.size_bytes()
+    }
+
+    ///
+    /// Raw bytes of the cache file, for parsing it without this crate.
+    ///
+    /// The bytes are read only and live as long as the cache, they may be a
+    /// memory mapped file which changes when the file is rewritten in place.
+    ///
+    #[cfg(feature = "raw-access")]
+    pub fn as_raw_bytes(&self) -> &[u8] {
+        self.storage.as_bytes()
+    }
+
+    fn reader(&self) -> Reader<'_> {
+        Reader::new(&self.storage, self.load_options.byte_order)
+    }
+
+    fn header(&self) -> Header {
+        Header {
+            major_version: self.major_version,
+            minor_version: self.minor_version,
+            hash_offset: self.hash_offset,
+            directory_list_offset: self.directory_list_offset,
+            n_buckets: self.n_buckets,
+            n_directories: self.n_directorys,
         }
     }
 
+    fn read_card16_from(&self, offset: usize) -> Option<usize> {
+        self.reader().card16(offset)
+    }
+
+    fn read_card32_from(&self, offset: usize) -> Option<usize> {
+        self.reader().card32(offset)
+    }
+
     fn read_cstring_from(&self, offset: usize) -> Option<String> {
-        let mut terminate = offset;
+        let bytes = self.read_cstring_bytes_from(offset)?;
 
-        while self.file_mmap[terminate] != b'\0' { terminate += 1; }
+        if bytes.is_empty() { return None; }
 
-        if terminate == offset { return None; }
+        Some(String::from_utf8_lossy(bytes).to_string())
+    }
 
-        Some(String::from_utf8_lossy(&self.file_mmap[offset..terminate]).to_string())
+    fn read_cstring_bytes_from(&self, offset: usize) -> Option<&[u8]> {
+        self.reader().cstring(offset)
     }
 
     ///
-    /// Look up an icon.
+    /// Compare the string at `offset` with `target` in place, an empty
+    /// string never matches.
     ///
-    /// * `name` - icon name.
+    fn cstring_matches(&self, offset: usize, target: &[u8]) -> bool {
+        if target.is_empty() || offset + target.len() >= self.storage.len() {
+            return false;
+        }
+
+        &self.storage[offset..offset + target.len()] == target && self.storage[offset + target.len()] == b'\0'
+    }
+
     ///
-    pub fn lookup<T: AsRef<str>>(&self, name: T) -> Option<Vec<&String>> {
-        let icon_hash = icon_name_hash(name.as_ref());
-        let bucket_index = icon_hash % self.n_buckets;
+    /// Name bytes and image list of every icon, in hash table order.
+    ///
+    pub(crate) fn raw_entries(&self) -> impl Iterator<Item = (&[u8], &[(usize, u16)])> {
+        self.entries.iter().filter_map(move |&(name_offset, list_offset)| {
+            let images = self.image_lists.get(&list_offset).map_or(&[][..], |x| x.as_slice());
+
+            Some((self.read_cstring_bytes_from(name_offset)?, images))
+        })
+    }
+
+    ///
+    /// Icons with their directories and image types, leaving out names and
+    /// directories which are not valid UTF-8 and icons without a directory.
+    /// Only the first entry of a name is kept, as `lookup` finds it.
+    ///
+    pub(crate) fn decoded_icons(&self) -> Vec<(&str, Vec<(&str, ImageTypeFlags)>)> {
+        let mut r = Vec::with_capacity(self.entries.len());
+        let mut added = HashSet::new();
 
-        let mut bucket_offset = self.read_card32_from(self.hash_offset + 4 + bucket_index * 4)?;
-        while let Some(bucket_name_offset) = self.read_card32_from(bucket_offset + 4) {
-            // read bucket name
-            if let Some(cache) = self.read_cstring_from(bucket_name_offset) {
-                if cache == name.as_ref() {
-                    let list_offset = self.read_card32_from(bucket_offset + 8)?;
-                    let list_len = self.read_card32_from(list_offset)?;
+        for (name, images) in self.raw_entries() {
+            let name = match ::std::str::from_utf8(name) {
+                Ok(name) if !name.is_empty() && !added.contains(name) => name,
+                _ => continue,
+            };
 
-                    let mut r = HashSet::with_capacity(list_len);
-                    // read cached dirs
-                    for i in 0..list_len {
-                        if let Some(dir_index) = self.read_card16_from(list_offset + 4 + 8 * i) {
-                            if let Some(offset) = self.read_card32_from(self.directory_list_offset + 4 + dir_index * 4) {
-                                r.insert(offset);
-                            }
-                        }
+            let mut dirs: Vec<(&str, ImageTypeFlags)> = Vec::new();
+            for &(dir_index, flags) in images {
+                if let Some(dir) = self.utf8_directory_by_index(dir_index) {
+                    let flags = ImageTypeFlags::from_bits_truncate(flags);
+                    match dirs.iter_mut().find(|x| x.0 == dir) {
+                        Some(x) => x.1 |= flags,
+                        None => dirs.push((dir, flags)),
                     }
+                }
+            }
 
-                    let ref dir_names = self.dir_names;
-                    return Some(r.iter().map(|x| dir_names.get(&x).unwrap()).collect())
+            if !dirs.is_empty() {
+                added.insert(name);
+                r.push((name, dirs));
+            }
+        }
+
+        r
+    }
+
+    ///
+    /// Like `get_directory_by_index`, but `None` for names which are not
+    /// valid UTF-8 instead of converting them.
+    ///
+    pub(crate) fn utf8_directory_by_index(&self, idx: usize) -> Option<&str> {
+        let offset = *self.dir_offsets.get(idx)?;
+        if self.lossy_dir_names.contains_key(&offset) {
+            return None;
+        }
+
+        self.dir_name_at_offset(offset)
+    }
+
+    ///
+    /// Build a new in-memory cache with the readable parts of this one, for
+    /// repairing caches `validate` finds problems in.
+    ///
+    /// Icons or directories whose names are not valid UTF-8 are dropped, as
+    /// are image list records referring to missing directories and icons
+    /// left without any directory.
+    ///
+    pub fn sanitize(&self) -> Result<GtkIconCache, CacheError> {
+        let mut builder = GtkIconCacheBuilder::new();
+        let mut dir_indices = HashMap::new();
+
+        for (name, images) in self.raw_entries() {
+            let name = match ::std::str::from_utf8(name) {
+                Ok(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+
+            let mut dirs = Vec::new();
+            for &(dir_index, flags) in images {
+                let index = *dir_indices.entry(dir_index).or_insert_with(|| {
+                    self.utf8_directory_by_index(dir_index).map(|x| builder.add_directory(x))
+                });
+
+                if let Some(index) = index {
+                    dirs.push((index, ImageTypeFlags::from_bits_truncate(flags)));
                 }
             }
 
-            // find in next
-            bucket_offset = self.read_card32_from(bucket_offset)?;
+            if !dirs.is_empty() {
+                builder.add_icon(name, &dirs);
+            }
         }
 
-        // not found
-        None
+        GtkIconCache::with_bytes_owned(builder.build())
     }
-}
 
-fn icon_name_hash<T: AsRef<str>>(name: T) -> usize {
+    ///
+    /// Check consistency of the cache, returning all problems found.
+    ///
+    pub fn validate(&self) -> Result<(), Vec<CacheError>> {
+        let mut errors = Vec::new();
 
-    let name = name.as_ref().as_bytes();
+        if self.hash_offset + 4 + self.n_buckets * 4 > self.storage.len() {
+            errors.push(CacheError::CorruptHashTable);
+            return Err(errors);
+        }
 
-    name.iter()
-        .fold(Wrapping(0u32), |r, &c| (r << 5) - r + Wrapping(c as u32)).0
-        as usize
-}
+        // directories
+        let n_directorys = self.n_directorys;
+        for i in 0..n_directorys {
+            match self.read_card32_from(self.directory_list_offset + 4 + 4 * i) {
+                Some(offset) => self.validate_string(offset, &mut errors),
+                None => errors.push(CacheError::InvalidOffset { offset: self.directory_list_offset + 4 + 4 * i }),
+            }
+        }
 
-#[cfg(test)]
-mod test {
+        // icons
+        let mut visited = HashSet::new();
+        for bucket_index in 0..self.n_buckets {
+            let mut entry_offset = self.read_card32_from(self.hash_offset + 4 + bucket_index * 4).unwrap();
 
-    use GtkIconCache;
-    use icon_name_hash;
+            while entry_offset != EMPTY_OFFSET {
+                if entry_offset + 12 > self.storage.len() {
+                    errors.push(CacheError::InvalidOffset { offset: entry_offset });
+                    break;
+                }
 
-    #[test]
-    fn test_icon_cache() {
-        let path = "test/caches/icon-theme.cache";
-        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+                if !visited.insert(entry_offset) {
+                    errors.push(CacheError::CorruptHashTable);
+                    break;
+                }
 
-        let icon_name = "web-browser";
-        let icon_hash = icon_name_hash(icon_name);
+                let name_offset = self.read_card32_from(entry_offset + 4).unwrap();
+                self.validate_string(name_offset, &mut errors);
 
-        assert_eq!(icon_hash, 2769241519);
-        assert_eq!(icon_cache.hash_offset, 12);
+                let list_offset = self.read_card32_from(entry_offset + 8).unwrap();
+                self.validate_image_list(list_offset, n_directorys, &mut errors);
 
-        println!("=> {:?}", icon_cache.lookup(icon_name));
+                entry_offset = self.read_card32_from(entry_offset).unwrap();
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 
-    #[test]
-    fn test_cache_test1() {
-        let path = "test/caches/test1.cache";
-        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+    fn validate_string(&self, offset: usize, errors: &mut Vec<CacheError>) {
+        match self.read_cstring_bytes_from(offset) {
+            Some(bytes) => if ::std::str::from_utf8(bytes).is_err() {
+                errors.push(CacheError::InvalidUtf8 { offset });
+            },
+            None => errors.push(CacheError::InvalidOffset { offset }),
+        }
+    }
 
-        let dirs = icon_cache.lookup("test").unwrap();
-        assert!(dirs.contains(&&"apps/32".to_string()));
-        assert!(dirs.contains(&&"apps/48".to_string()));
+    fn validate_image_list(&self, list_offset: usize, n_directorys: usize, errors: &mut Vec<CacheError>) {
+        let list_len = match self.read_card32_from(list_offset) {
+            Some(len) => len,
+            None => {
+                errors.push(CacheError::InvalidOffset { offset: list_offset });
+                return;
+            },
+        };
 
-        let dirs = icon_cache.lookup("deepin-deb-installer").unwrap();
-        assert!(dirs.contains(&&"apps/16".to_string()));
-        assert!(dirs.contains(&&"apps/32".to_string()));
-        assert!(dirs.contains(&&"apps/48".to_string()));
-        assert!(dirs.contains(&&"apps/scalable".to_string()));
+        if list_offset + 4 + 8 * list_len > self.storage.len() {
+            errors.push(CacheError::InvalidOffset { offset: list_offset });
+            return;
+        }
+
+        for i in 0..list_len {
+            let dir_index = self.read_card16_from(list_offset + 4 + 8 * i).unwrap();
+            if dir_index >= n_directorys {
+                errors.push(CacheError::InvalidDirectoryIndex { index: dir_index });
+            }
+        }
     }
 
-    #[test]
-    fn test_icon_name_hash() {
-        assert_eq!(icon_name_hash("deepin-deb-installer"), 1927089920);
+    ///
+    /// Look up an icon.
+    ///
+    /// * `name` - icon name.
+    ///
+    #[cfg_attr(feature = "tracing", instrument(level = "trace", skip_all, fields(name = name.as_ref())))]
+    pub fn lookup<T: AsRef<str>>(&self, name: T) -> Option<Vec<&str>> {
+        self.lookup_bytes(name.as_ref().as_bytes())
+    }
+
+    ///
+    /// Same as `lookup`, named like the getters of std collections.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn get<T: AsRef<str>>(&self, name: T) -> Option<Vec<&str>> {
+        self.lookup(name)
+    }
+
+    ///
+    /// Look up an icon by its raw name, which may not be valid UTF-8.
+    ///
+    /// * `name` - icon name bytes.
+    ///
+    pub fn lookup_bytes(&self, name: &[u8]) -> Option<Vec<&str>> {
+        let list_offset = self.find_list_offset(name)?;
+
+        self.read_dirs(list_offset)
+    }
+
+    ///
+    /// Read directory names from the image list at `list_offset`, `None`
+    /// if no directory index of the list is in range.
+    ///
+    fn read_dirs(&self, list_offset: usize) -> Option<Vec<&str>> {
+        let images = self.image_lists.get(&list_offset)?;
+
+        let mut r = Vec::with_capacity(images.len());
+        for dir in images.iter().filter_map(|x| self.get_directory_by_index(x.0)) {
+            if !r.contains(&dir) {
+                r.push(dir);
+            }
+        }
+
+        if r.is_empty() && !images.is_empty() {
+            return None;
+        }
+
+        Some(r)
+    }
+
+    ///
+    /// Read `(dir_index, flags)` of each readable record of the image list
+    /// at `list_offset`.
+    ///
+    fn read_image_list(&self, list_offset: usize) -> Option<Vec<(usize, u16)>> {
+        let list_len = self.read_card32_from(list_offset)?;
+
+        let mut r = Vec::new();
+        for i in 0..list_len {
+            let image_offset = list_offset + 4 + 8 * i;
+            match (self.read_card16_from(image_offset), self.read_card16_from(image_offset + 2)) {
+                (Some(dir_index), Some(flags)) => r.push((dir_index, flags as u16)),
+                _ => break,
+            }
+        }
+
+        Some(r)
+    }
+
+    ///
+    /// Name of the directory at `idx` of the directory list, names which are
+    /// not valid UTF-8 are converted lossily.
+    ///
+    /// * `idx` - directory index, as stored in image lists.
+    ///
+    pub fn get_directory_by_index(&self, idx: usize) -> Option<&str> {
+        self.dir_name_at_offset(*self.dir_offsets.get(idx)?)
+    }
+
+    ///
+    /// Directory name whose string is at `offset`, borrowed from the cache
+    /// data unless it was converted when loading.
+    ///
+    fn dir_name_at_offset(&self, offset: usize) -> Option<&str> {
+        if let Some(name) = self.lossy_dir_names.get(&offset) {
+            return Some(name);
+        }
+
+        match self.read_cstring_bytes_from(offset) {
+            Some(bytes) if !bytes.is_empty() => ::std::str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Look up an icon, returning owned directory names.
+    ///
+    /// Prefer `lookup` unless the result must outlive the cache.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_owned<T: AsRef<str>>(&self, name: T) -> Option<Vec<String>> {
+        self.lookup(name).map(|dirs| dirs.into_iter().map(|x| x.to_string()).collect())
+    }
+
+    ///
+    /// Look up an icon, retrying with the name in ASCII lower case and then
+    /// upper case when it is not found.
+    ///
+    /// This is a best-effort fallback for badly named icons, costing up to
+    /// two extra lookups, it is no replacement for correct names.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_case_insensitive<T: AsRef<str>>(&self, name: T) -> Option<Vec<&str>> {
+        with_case_fallback(name.as_ref(), |x| self.lookup(x))
+    }
+
+    ///
+    /// Check if an icon is in a specific directory.
+    ///
+    /// * `name` - icon name.
+    /// * `dir` - directory name, e.g. `apps/48`.
+    ///
+    pub fn lookup_in_directory<T: AsRef<str>>(&self, name: T, dir: &str) -> bool {
+        self.lookup(name).is_some_and(|dirs| dirs.contains(&dir))
+    }
+
+    ///
+    /// Look up an icon, returning the first directory of `preferred` it is in.
+    ///
+    /// * `name` - icon name.
+    /// * `preferred` - directory names, most preferred first.
+    ///
+    pub fn lookup_preferred_directory<T: AsRef<str>>(&self, name: T, preferred: &[&str]) -> Option<&str> {
+        let dirs = self.lookup(name)?;
+
+        preferred.iter()
+            .filter_map(|p| dirs.iter().find(|x| *x == p))
+            .next()
+            .cloned()
+    }
+
+    ///
+    /// Look up an icon, returning the first of `preferred_dirs` containing
+    /// it, or else the first directory `lookup` returns.
+    ///
+    /// * `name` - icon name.
+    /// * `preferred_dirs` - directory names, most preferred first.
+    ///
+    pub fn lookup_multi_dir<T: AsRef<str>>(&self, name: T, preferred_dirs: &[&str]) -> Option<&str> {
+        let dirs = self.lookup(name)?;
+
+        preferred_dirs.iter()
+            .filter_map(|p| dirs.iter().find(|x| *x == p))
+            .chain(dirs.first())
+            .next()
+            .cloned()
+    }
+
+    ///
+    /// Look up an icon, returning the directory best fitting `size`.
+    ///
+    /// The largest directory not larger than `size` is preferred, then the
+    /// smallest larger one. Scalable directories are treated as infinitely
+    /// large, directories without a size in their name are ignored.
+    ///
+    /// * `name` - icon name.
+    /// * `size` - icon size in pixels.
+    ///
+    pub fn lookup_for_size<T: AsRef<str>>(&self, name: T, size: u32) -> Option<&str> {
+        let sized: Vec<_> = self.lookup(name)?
+            .into_iter()
+            .filter_map(|dir| dir_size(dir).map(|x| (x, dir)))
+            .collect();
+
+        let not_larger = sized.iter().filter(|x| x.0 <= size).max_by_key(|x| x.0);
+        let smallest = sized.iter().min_by_key(|x| x.0);
+
+        not_larger.or(smallest).map(|x| x.1)
+    }
+
+    ///
+    /// Look up an icon and find its file in `theme_dir`, trying
+    /// `<theme_dir>/<dir>/<name>.svg`, then `.png`, then `.xpm` for each
+    /// directory in cache order. The first existing file is returned.
+    ///
+    /// * `name` - icon name.
+    /// * `theme_dir` - icon theme directory of the cache.
+    ///
+    pub fn lookup_icon_file_path<T: AsRef<str>, P: AsRef<Path>>(&self, name: T, theme_dir: P) -> Option<PathBuf> {
+        let name = name.as_ref();
+
+        for dir in self.lookup(name)? {
+            for ext in &["svg", "png", "xpm"] {
+                let path = theme_dir.as_ref().join(dir).join(format!("{}.{}", name, ext));
+                if path.exists() {
+                    return Some(path);
+                }
+            }
+        }
+
+        None
+    }
+
+    ///
+    /// Look up an icon, with directories closest in size to `size` first.
+    /// Scalable directories are treated as infinitely large, directories
+    /// without a size in their name come last. Equally close directories
+    /// keep cache order.
+    ///
+    /// * `name` - icon name.
+    /// * `size` - icon size in pixels.
+    ///
+    pub fn lookup_with_size_hint<T: AsRef<str>>(&self, name: T, size: u32) -> Option<Vec<&str>> {
+        let mut dirs = self.lookup(name)?;
+
+        dirs.sort_by_key(|dir| dir_size(dir).map_or(u32::MAX as u64 + 1, |x| x.abs_diff(size) as u64));
+
+        Some(dirs)
+    }
+
+    ///
+    /// Look up an icon, with scalable directories first, then sized ones
+    /// from the largest to the smallest. Directories without a size in their
+    /// name come last, in cache order.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_scalable_preferred<T: AsRef<str>>(&self, name: T) -> Option<Vec<&str>> {
+        let mut dirs = self.lookup(name)?;
+
+        dirs.sort_by_key(|dir| Reverse((dir.contains("scalable"), dir_size(dir))));
+
+        Some(dirs)
+    }
+
+    ///
+    /// Look up an icon, also returning image types in each directory.
+    ///
+    /// Each directory is returned once, with flags of all its image list
+    /// records combined.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_with_flags<T: AsRef<str>>(&self, name: T) -> Option<Vec<IconDirEntry<'_>>> {
+        let entries = self.lookup_all_with_flags(name)?;
+
+        let mut r: Vec<IconDirEntry> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match r.iter_mut().find(|x| x.dir == entry.dir) {
+                Some(x) => x.flags |= entry.flags,
+                None => r.push(entry),
+            }
+        }
+
+        Some(r)
+    }
+
+    ///
+    /// Look up an icon, returning raw directory indices of its image list
+    /// records in cache order. Indices are not checked or deduplicated, pass
+    /// them to `get_directory_by_index` for names.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_with_dir_indices<T: AsRef<str>>(&self, name: T) -> Option<Vec<usize>> {
+        let list_offset = self.find_list_offset(name.as_ref().as_bytes())?;
+        let images = self.image_lists.get(&list_offset)?;
+
+        Some(images.iter().map(|x| x.0).collect())
+    }
+
+    ///
+    /// Look up an icon, also returning the raw image type bits in each
+    /// directory, to be masked with the `ICON_TYPE_*` constants.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_flags<T: AsRef<str>>(&self, name: T) -> Option<Vec<(&str, u16)>> {
+        let list_offset = self.find_list_offset(name.as_ref().as_bytes())?;
+        let images = self.image_lists.get(&list_offset)?;
+
+        let mut r: Vec<(&str, u16)> = Vec::with_capacity(images.len());
+        for &(dir_index, flags) in images {
+            let dir = match self.get_directory_by_index(dir_index) {
+                Some(dir) => dir,
+                None => continue,
+            };
+
+            match r.iter_mut().find(|x| x.0 == dir) {
+                Some(x) => x.1 |= flags,
+                None => r.push((dir, flags)),
+            }
+        }
+
+        Some(r)
+    }
+
+    ///
+    /// Look up an icon, returning every record of its image list with the
+    /// image types it has, in the order they are stored.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_all_with_flags<T: AsRef<str>>(&self, name: T) -> Option<Vec<IconDirEntry<'_>>> {
+        let list_offset = self.find_list_offset(name.as_ref().as_bytes())?;
+        let images = self.image_lists.get(&list_offset)?;
+
+        Some(images.iter()
+            .filter_map(|&(dir_index, flags)| {
+                let dir = self.get_directory_by_index(dir_index)?;
+                Some(IconDirEntry { dir, flags: ImageTypeFlags::from_bits_truncate(flags) })
+            })
+            .collect())
+    }
+
+    ///
+    /// Look up an icon with `opts` changing how it is looked up.
+    ///
+    /// * `name` - icon name.
+    /// * `opts` - lookup options.
+    ///
+    pub fn lookup_with_options<T: AsRef<str>>(&self, name: T, opts: &LookupOptions) -> Option<Vec<IconDirEntry<'_>>> {
+        let name = name.as_ref();
+
+        let mut r = if opts.case_insensitive {
+            with_case_fallback(name, |x| self.lookup_with_flags(x))?
+        } else {
+            self.lookup_with_flags(name)?
+        };
+
+        if opts.prefer_svg {
+            r.sort_by_key(|x| !x.flags.contains(ImageTypeFlags::SVG));
+        }
+
+        if let Some(max_dirs) = opts.max_dirs {
+            r.truncate(max_dirs);
+        }
+
+        Some(r)
+    }
+
+    ///
+    /// Look up an icon, returning only directories with a `.svg` file of it.
+    ///
+    /// Returns `None` if the icon is not in the cache.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_svg_only<T: AsRef<str>>(&self, name: T) -> Option<Vec<&str>> {
+        self.lookup_image_type(name.as_ref(), ImageTypeFlags::SVG)
+    }
+
+    ///
+    /// Look up an icon, returning only directories with a `.png` file of it.
+    ///
+    /// Returns `None` if the icon is not in the cache.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn lookup_png_only<T: AsRef<str>>(&self, name: T) -> Option<Vec<&str>> {
+        self.lookup_image_type(name.as_ref(), ImageTypeFlags::PNG)
+    }
+
+    fn lookup_image_type(&self, name: &str, image_type: ImageTypeFlags) -> Option<Vec<&str>> {
+        let entries = self.lookup_with_flags(name)?;
+
+        Some(entries.into_iter()
+            .filter(|x| x.flags.contains(image_type))
+            .map(|x| x.dir)
+            .collect())
+    }
+
+    ///
+    /// Bytes used by the records of an icon: its 12 byte hash table entry,
+    /// its name with the terminating NUL, the 4 byte image list length and 8
+    /// bytes per image list record. Alignment padding is not counted.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn entry_size_bytes<T: AsRef<str>>(&self, name: T) -> Option<usize> {
+        let name = name.as_ref();
+        let list_offset = self.find_list_offset(name.as_bytes())?;
+        let list_len = self.read_card32_from(list_offset)?;
+
+        Some(12 + name.len() + 1 + 4 + 8 * list_len)
+    }
+
+    ///
+    /// Check if an icon is in the cache, without reading its directories.
+    ///
+    /// * `name` - icon name.
+    ///
+    pub fn contains<T: AsRef<str>>(&self, name: T) -> bool {
+        self.find_list_offset(name.as_ref().as_bytes()).is_some()
+    }
+
+    ///
+    /// Iterate over `(icon_name, dirs)` of all icons in the cache.
+    ///
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self)
+    }
+
+    ///
+    /// Number of icons in the cache.
+    ///
+    pub fn icon_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    ///
+    /// Average number of icons per bucket.
+    ///
+    pub fn hash_load_factor(&self) -> f64 {
+        if self.n_buckets == 0 {
+            return 0.0;
+        }
+
+        self.icon_count() as f64 / self.n_buckets as f64
+    }
+
+    ///
+    /// Number of icons which are not the first in their bucket chain, high
+    /// values compared to `n_buckets` mean the hash table is too small.
+    ///
+    pub fn collision_count(&self) -> usize {
+        self.collision_count
+    }
+
+    ///
+    /// Average chain length of non-empty buckets, this scans the whole cache.
+    ///
+    pub fn avg_chain_length(&self) -> f64 {
+        self.stats().avg_chain_length
+    }
+
+    ///
+    /// Statistics of the cache.
+    ///
+    /// This walks every bucket chain once, taking O(n_buckets + n_icons) time.
+    ///
+    pub fn stats(&self) -> CacheStats {
+        CacheStats::new(&self.chain_lengths(), self.n_directorys, self.storage.len())
+    }
+
+    ///
+    /// Number of icons in each bucket, to see how well icon names spread
+    /// over the hash table. This scans the whole cache.
+    ///
+    pub fn icon_name_hash_distribution(&self) -> Vec<usize> {
+        self.chain_lengths()
+    }
+
+    ///
+    /// Print a histogram of how many buckets have each chain length to
+    /// stdout, see `icon_name_hash_distribution`.
+    ///
+    pub fn print_hash_distribution(&self) {
+        print!("{}", self.hash_distribution_histogram());
+    }
+
+    fn hash_distribution_histogram(&self) -> String {
+        const BAR_WIDTH: usize = 40;
+
+        let chain_lengths = self.chain_lengths();
+        let mut n_buckets = vec![0; chain_lengths.iter().cloned().max().unwrap_or(0) + 1];
+        for &length in &chain_lengths {
+            n_buckets[length] += 1;
+        }
+
+        let max = n_buckets.iter().cloned().max().unwrap_or(0).max(1);
+        let width = (n_buckets.len() - 1).to_string().len();
+
+        let mut r = String::new();
+        for (length, &n) in n_buckets.iter().enumerate() {
+            let bar = "#".repeat(n * BAR_WIDTH / max + usize::from(n > 0 && n * BAR_WIDTH < max));
+            r.push_str(&format!("{:>width$} | {} {}\n", length, bar, n, width = width));
+        }
+
+        r
+    }
+
+    ///
+    /// Number of icons in each bucket.
+    ///
+    fn chain_lengths(&self) -> Vec<usize> {
+        let mut r = vec![0; self.n_buckets];
+        self.walk_buckets(|bucket_index, _| r[bucket_index] += 1);
+
+        r
+    }
+
+    ///
+    /// Copy all icons into a map of icon name to directories, this scans the
+    /// whole cache. The map does not borrow the cache, so it can outlive it.
+    ///
+    /// If a name appears more than once, the directories `lookup` returns
+    /// for it are kept.
+    ///
+    pub fn to_hashmap(&self) -> HashMap<String, Vec<String>> {
+        let mut r = HashMap::with_capacity(self.icon_count());
+
+        for (name, dirs) in self {
+            r.entry(name).or_insert(dirs);
+        }
+
+        r
+    }
+
+    ///
+    /// List names of all icons in the cache.
+    ///
+    pub fn list_all_icons(&self) -> Vec<String> {
+        self.entries.iter()
+            .filter_map(|&(name_offset, _)| self.read_cstring_from(name_offset))
+            .collect()
+    }
+
+    ///
+    /// Look up all icons whose names start with `prefix`, this scans the whole cache.
+    ///
+    /// * `prefix` - icon name prefix, e.g. `emblem-`.
+    ///
+    pub fn lookup_prefix(&self, prefix: &str) -> Vec<(String, Vec<&str>)> {
+        let mut r = Vec::new();
+
+        self.walk_entries(|entry_offset| {
+            let name = match self.read_card32_from(entry_offset + 4).and_then(|x| self.read_cstring_from(x)) {
+                Some(name) => name,
+                None => return,
+            };
+
+            if !name.starts_with(prefix) {
+                return;
+            }
+
+            if let Some(dirs) = self.read_card32_from(entry_offset + 8).and_then(|x| self.read_dirs(x)) {
+                r.push((name, dirs));
+            }
+        });
+
+        r
+    }
+
+    ///
+    /// Count icons in each directory, this scans the whole cache. Directories
+    /// without icons are counted as 0.
+    ///
+    pub fn count_icons_per_directory(&self) -> HashMap<&str, usize> {
+        let mut r: HashMap<&str, usize> = self.directories().map(|x| (x, 0)).collect();
+
+        for &(_, list_offset) in &self.entries {
+            for dir in self.read_dirs(list_offset).unwrap_or_default() {
+                *r.entry(dir).or_insert(0) += 1;
+            }
+        }
+
+        r
+    }
+
+    ///
+    /// List names of all icons found in a directory, this scans the whole cache.
+    ///
+    /// * `dir` - directory name, e.g. `apps/48`.
+    ///
+    pub fn icons_in_directory(&self, dir: &str) -> Vec<String> {
+        let mut r = Vec::new();
+
+        self.walk_entries(|entry_offset| {
+            let in_dir = self.read_card32_from(entry_offset + 8)
+                .and_then(|list_offset| self.read_dirs(list_offset))
+                .is_some_and(|dirs| dirs.contains(&dir));
+
+            if in_dir {
+                if let Some(name) = self.read_card32_from(entry_offset + 4).and_then(|x| self.read_cstring_from(x)) {
+                    r.push(name);
+                }
+            }
+        });
+
+        r
+    }
+
+    ///
+    /// List names of all directories in the cache, in directory index order.
+    ///
+    pub fn list_directories(&self) -> Vec<&str> {
+        self.directories().collect()
+    }
+
+    ///
+    /// List names of directories containing at least one icon, in directory
+    /// index order. Unlike `list_directories`, directories in the directory
+    /// list without icons are left out. This scans the whole cache.
+    ///
+    pub fn lookup_all_dirs(&self) -> Vec<&str> {
+        let mut used = vec![false; self.n_directorys];
+        for &(_, list_offset) in &self.entries {
+            for &(dir_index, _) in self.image_lists.get(&list_offset).into_iter().flatten() {
+                if let Some(x) = used.get_mut(dir_index) {
+                    *x = true;
+                }
+            }
+        }
+
+        (0..self.n_directorys)
+            .filter(|&i| used[i])
+            .filter_map(|i| self.get_directory_by_index(i))
+            .collect()
+    }
+
+    ///
+    /// Iterate over names of all directories in the cache, in directory
+    /// index order, without collecting them.
+    ///
+    pub fn directories(&self) -> impl Iterator<Item = &str> + '_ {
+        (0..self.n_directorys).filter_map(move |i| self.get_directory_by_index(i))
+    }
+
+    ///
+    /// Call `f` with offset of every icon entry in the hash table, each
+    /// entry is visited once even if chains are corrupted into cycles.
+    ///
+    fn walk_entries<F: FnMut(usize)>(&self, mut f: F) {
+        self.walk_buckets(|_, entry_offset| f(entry_offset))
+    }
+
+    ///
+    /// Like `walk_entries`, also passing the bucket index of each entry.
+    ///
+    fn walk_buckets<F: FnMut(usize, usize)>(&self, f: F) {
+        self.reader().walk_buckets(self.hash_offset, self.n_buckets, f)
+    }
+
+    ///
+    /// Look up many icons at once, each bucket chain is walked only once.
+    ///
+    /// * `names` - icon names.
+    ///
+    pub fn lookup_many<'a, 'b>(&'a self, names: &[&'b str]) -> HashMap<&'b str, Option<Vec<&'a str>>> {
+        let mut r = HashMap::with_capacity(names.len());
+        let mut buckets: HashMap<usize, Vec<&str>> = HashMap::new();
+
+        for &name in names {
+            if r.insert(name, None).is_none() {
+                if let Some(bucket_index) = self.bucket_index(name.as_bytes()) {
+                    buckets.entry(bucket_index).or_default().push(name);
+                }
+            }
+        }
+
+        for (bucket_index, wanted) in buckets {
+            let mut n_found = 0;
+
+            self.walk_chain(bucket_index, |entry_offset, name_offset| {
+                if let Some(&name) = wanted.iter().find(|x| self.cstring_matches(name_offset, x.as_bytes())) {
+                    let dirs = self.read_card32_from(entry_offset + 8).and_then(|x| self.read_dirs(x));
+                    r.insert(name, dirs);
+                    n_found += 1;
+                }
+
+                // stop once all names in this bucket are found
+                n_found == wanted.len()
+            });
+        }
+
+        r
+    }
+
+    ///
+    /// Find the image list offset of an icon by walking its bucket chain.
+    ///
+    fn find_list_offset(&self, name: &[u8]) -> Option<usize> {
+        if let Some(&list_offset) = ::std::str::from_utf8(name).ok().and_then(|x| self.preloaded.get(x)) {
+            return Some(list_offset);
+        }
+
+        let bucket_index = self.bucket_index(name)?;
+
+        let mut r = None;
+        self.walk_chain(bucket_index, |entry_offset, name_offset| {
+            if self.cstring_matches(name_offset, name) {
+                r = self.read_card32_from(entry_offset + 8);
+                return true;
+            }
+
+            false
+        });
+
+        r
+    }
+
+    ///
+    /// Index of the bucket an icon belongs to, `None` if there is no bucket.
+    ///
+    fn bucket_index(&self, name: &[u8]) -> Option<usize> {
+        if self.n_buckets == 0 {
+            return None;
+        }
+
+        let hash = match self.load_options.hash_fn {
+            Some(hash_fn) => hash_fn(&String::from_utf8_lossy(name)),
+            None => icon_name_bytes_hash(name) as usize,
+        };
+
+        Some(hash % self.n_buckets)
+    }
+
+    ///
+    /// Call `f` with offsets of each entry and its name in a bucket chain,
+    /// until `f` returns `true` or the chain ends.
+    ///
+    fn walk_chain<F: FnMut(usize, usize) -> bool>(&self, bucket_index: usize, f: F) {
+        self.reader().walk_chain(self.hash_offset, bucket_index, f)
+    }
+}
+
+///
+/// Caches are equal when their data is equal, no matter where it is loaded from.
+///
+#[cfg(feature = "std")]
+impl PartialEq for GtkIconCache {
+    fn eq(&self, other: &Self) -> bool {
+        self.n_buckets == other.n_buckets &&
+        self.hash_offset == other.hash_offset &&
+        self.directory_list_offset == other.directory_list_offset &&
+        self.storage[..] == other.storage[..]
+    }
+}
+
+#[cfg(feature = "std")]
+impl Eq for GtkIconCache {}
+
+///
+/// Hashes the cache data, so caches loaded from different paths with the same
+/// content hash equally.
+///
+#[cfg(feature = "std")]
+impl Hash for GtkIconCache {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.storage[..].hash(state);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> TryFrom<&'a Path> for GtkIconCache {
+    type Error = CacheError;
+
+    fn try_from(path: &'a Path) -> Result<Self, CacheError> {
+        Self::with_file_path(path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<PathBuf> for GtkIconCache {
+    type Error = CacheError;
+
+    fn try_from(path: PathBuf) -> Result<Self, CacheError> {
+        Self::with_file_path(path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for GtkIconCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GtkIconCache {{ version: {}.{}, buckets: {}, directories: {}",
+               self.major_version, self.minor_version, self.n_buckets, self.dir_offsets.len())?;
+
+        if let Some(ref path) = self.source_path {
+            write!(f, ", source: {}", path.display())?;
+        }
+
+        write!(f, " }}")
+    }
+}
+
+///
+/// Icon size of a directory parsed from names like `apps/48`, `48x48/apps`
+/// or `apps/scalable`, scalable directories have size `u32::MAX`. HiDPI
+/// directories like `48x48@2x/apps` have their size in pixels, here 96.
+///
+#[cfg(feature = "std")]
+fn dir_size(dir: &str) -> Option<u32> {
+    dir.split('/')
+        .filter_map(|component| {
+            if component == "scalable" {
+                return Some(u32::MAX);
+            }
+
+            let (component, scale) = match component.split_once('@') {
+                Some((size, scale)) => (size, scale.strip_suffix('x')?.parse::<u32>().ok()?),
+                None => (component, 1),
+            };
+
+            let mut sizes = component.split('x');
+            let size = sizes.next()?.parse::<u32>().ok()?;
+            match sizes.next() {
+                Some(height) if height.parse::<u32>().ok()? != size => None,
+                _ => size.checked_mul(scale),
+            }
+        })
+        .next()
+}
+
+///
+/// Run `lookup` with `name`, retrying with it in ASCII lower case and then
+/// upper case while nothing is found.
+///
+#[cfg(feature = "std")]
+fn with_case_fallback<R, F: Fn(&str) -> Option<R>>(name: &str, lookup: F) -> Option<R> {
+    lookup(name)
+        .or_else(|| lookup(&name.to_ascii_lowercase()))
+        .or_else(|| lookup(&name.to_ascii_uppercase()))
+}
+
+///
+/// Read the format version of a cache file as `(major, minor)`, only the
+/// first 4 bytes are read. The version is returned even if unsupported.
+///
+/// * `path` - Cache file path.
+///
+#[cfg(feature = "std")]
+pub fn detect_format_version<T: AsRef<Path>>(path: T) -> Result<(u16, u16), CacheError> {
+    let mut header = Vec::with_capacity(4);
+    File::open(path.as_ref())?.take(4).read_to_end(&mut header)?;
+
+    match header.len() {
+        0 | 1 => Err(CacheError::TruncatedData { offset: 0 }),
+        2 | 3 => Err(CacheError::TruncatedData { offset: 2 }),
+        _ => Ok((u16::from_be_bytes([header[0], header[1]]), u16::from_be_bytes([header[2], header[3]]))),
+    }
+}
+
+///
+/// Hash an icon name the same way GTK does, the bucket of an icon is
+/// `icon_name_hash(name) % n_buckets`.
+///
+/// * `name` - icon name.
+///
+pub fn icon_name_hash(name: &str) -> u32 {
+    icon_name_bytes_hash(name.as_bytes())
+}
+
+fn icon_name_bytes_hash(name: &[u8]) -> u32 {
+
+    // GTK hashes `signed char`s, bytes above 0x7f are sign extended
+    name.iter()
+        .fold(Wrapping(0u32), |r, &c| (r << 5) - r + Wrapping(c as i8 as u32)).0
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+
+    use GtkIconCache;
+    use GtkIconCacheBuilder;
+    use GtkIconCacheRef;
+    use GtkIconCacheSet;
+    use LoadOptions;
+    use LookupOptions;
+    use ByteOrder;
+    use dir_size;
+    use icon_name_bytes_hash;
+    use CacheError;
+    use ImageTypeFlags;
+    use {ICON_TYPE_PNG, ICON_TYPE_SVG};
+    use BackingStorage;
+    use icon_name_hash;
+    use IconCache;
+    use detect_format_version;
+    use MAX_NAME_LEN;
+    use EMPTY_OFFSET;
+
+    use std::collections::{HashMap, HashSet};
+    use std::convert::TryFrom;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    fn raw_cache(data: Vec<u8>) -> GtkIconCache {
+        GtkIconCache::unloaded(BackingStorage::Bytes(Arc::new(data)))
+    }
+
+    ///
+    /// Cache data with `icons`, each a name and its directories with image
+    /// types. Directories get indices in order of first use.
+    ///
+    fn build_cache_data(icons: &[(&str, &[(&str, ImageTypeFlags)])]) -> Vec<u8> {
+        let mut builder = GtkIconCacheBuilder::new();
+        for &(name, dirs) in icons {
+            let dirs: Vec<_> = dirs.iter().map(|&(dir, flags)| (builder.add_directory(dir), flags)).collect();
+            builder.add_icon(name, &dirs);
+        }
+
+        builder.build()
+    }
+
+    fn build_cache(icons: &[(&str, &[(&str, ImageTypeFlags)])]) -> GtkIconCache {
+        GtkIconCache::with_bytes_owned(build_cache_data(icons)).unwrap()
+    }
+
+    ///
+    /// Cache data with a single `firefox` icon in `apps/16`.
+    ///
+    fn firefox_cache_data() -> Vec<u8> {
+        build_cache_data(&[("firefox", &[("apps/16", ImageTypeFlags::PNG)])])
+    }
+
+    fn set_card32(data: &mut [u8], offset: usize, v: usize) {
+        data[offset..offset + 4].copy_from_slice(&(v as u32).to_be_bytes());
+    }
+
+    ///
+    /// Replace the next entry link of the first entry in the bucket of
+    /// `name` with `link` applied to the entry offset, returning the bucket.
+    ///
+    fn corrupt_chain_link<F: FnOnce(usize) -> usize>(data: &mut [u8], name: &str, link: F) -> usize {
+        let (bucket_index, entry_offset) = {
+            let icon_cache = GtkIconCache::with_bytes(data).unwrap();
+            let bucket_index = icon_cache.bucket_index(name.as_bytes()).unwrap();
+
+            (bucket_index, icon_cache.read_card32_from(icon_cache.hash_offset + 4 + bucket_index * 4).unwrap())
+        };
+
+        set_card32(data, entry_offset, link(entry_offset));
+        bucket_index
+    }
+
+    ///
+    /// Check lookups in a bucket holding only `firefox` end at its corrupted
+    /// chain link.
+    ///
+    fn check_corrupted_chain(data: Vec<u8>, bucket_index: usize) {
+        let icon_cache = GtkIconCache::with_bytes_owned(data).unwrap();
+
+        // find a missing name in the same bucket
+        let missing = (0..).map(|i| format!("missing-{}", i))
+            .find(|x| icon_name_hash(x) as usize % icon_cache.n_buckets() == bucket_index)
+            .unwrap();
+
+        assert!(icon_cache.contains("firefox"));
+        assert!(icon_cache.lookup(&missing).is_none());
+        assert_eq!(icon_cache.list_all_icons(), vec!["firefox"]);
+    }
+
+    #[test]
+    fn test_icon_cache() {
+        let path = "test/caches/icon-theme.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let icon_name = "web-browser";
+        let icon_hash = icon_name_hash(icon_name);
+
+        assert_eq!(icon_hash, 2769241519);
+        assert_eq!(icon_cache.hash_offset, 12);
+
+        println!("=> {:?}", icon_cache.lookup(icon_name));
+    }
+
+    #[test]
+    fn test_cache_test1() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let dirs = icon_cache.lookup("test").unwrap();
+        assert!(dirs.contains(&"apps/32"));
+        assert!(dirs.contains(&"apps/48"));
+
+        let dirs = icon_cache.lookup("deepin-deb-installer").unwrap();
+        assert!(dirs.contains(&"apps/16"));
+        assert!(dirs.contains(&"apps/32"));
+        assert!(dirs.contains(&"apps/48"));
+        assert!(dirs.contains(&"apps/scalable"));
+    }
+
+    #[test]
+    fn test_cache_version() {
+        let path = "test/caches/icon-theme.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.version(), (1, 0));
+    }
+
+    #[test]
+    fn test_cache_n_buckets() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.n_buckets(), 11);
+    }
+
+    #[test]
+    fn test_cache_collision_count() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.collision_count(), 0);
+    }
+
+    #[test]
+    fn test_cache_get() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.get("test").unwrap().len(), 2);
+        assert!(icon_cache.get("not-exist").is_none());
+    }
+
+    #[test]
+    fn test_cache_preload_all() {
+        let path = "test/caches/test1.cache";
+        let mut icon_cache = GtkIconCache::with_file_path(path).unwrap();
+        let expected = icon_cache.lookup("deepin-deb-installer").map(|x| x.len());
+
+        icon_cache.preload_all();
+        assert_eq!(icon_cache.preloaded.len(), 4);
+        assert_eq!(icon_cache.lookup("deepin-deb-installer").map(|x| x.len()), expected);
+        assert_eq!(icon_cache.lookup("test-48x").map(|x| x.len()), Some(1));
+        assert_eq!(icon_cache.lookup("not-exist"), None);
+    }
+
+    #[test]
+    fn test_cache_contains() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert!(icon_cache.contains("test"));
+        assert!(icon_cache.contains("deepin-deb-installer"));
+        assert!(!icon_cache.contains("not-exist"));
+    }
+
+    #[test]
+    fn test_cache_lookup_owned() {
+        let path = "test/caches/test1.cache";
+        let dirs = {
+            let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+            icon_cache.lookup_owned("test").unwrap()
+        };
+
+        assert!(dirs.contains(&"apps/32".to_string()));
+        assert!(dirs.contains(&"apps/48".to_string()));
+    }
+
+    #[test]
+    fn test_cache_lookup_case_insensitive() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.lookup("Test"), None);
+        assert_eq!(icon_cache.lookup_case_insensitive("Test").map(|x| x.len()), Some(2));
+        assert_eq!(icon_cache.lookup_case_insensitive("not-exist"), None);
+
+        let icon_cache = build_cache(&[("FIREFOX", &[("apps/48", ImageTypeFlags::PNG)])]);
+
+        assert_eq!(icon_cache.lookup_case_insensitive("Firefox").unwrap(), vec!["apps/48"]);
+    }
+
+    #[test]
+    fn test_cache_list_all_icons() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let mut icons = icon_cache.list_all_icons();
+        icons.sort();
+        assert_eq!(icons, vec!["deepin-deb-installer", "name.with.dot", "test", "test-48x"]);
+        assert_eq!(icon_cache.icon_count(), 4);
+        assert_eq!(icon_cache.hash_load_factor(), 4.0 / 11.0);
+        assert_eq!(icon_cache.avg_chain_length(), 1.0);
+
+        let stats = icon_cache.stats();
+        assert_eq!(stats.n_buckets, 11);
+        assert_eq!(stats.n_icons, 4);
+        assert_eq!(stats.n_directories, 4);
+        assert_eq!(stats.empty_buckets, 7);
+        assert_eq!(stats.max_chain_length, 1);
+        assert_eq!(stats.file_size_bytes, 308);
+        assert_eq!(icon_cache.size_bytes(), 308);
+        assert_eq!(icon_cache.backing_data_len(), 308);
+    }
+
+    #[test]
+    fn test_cache_hash_distribution() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let distribution = icon_cache.icon_name_hash_distribution();
+        assert_eq!(distribution.len(), 11);
+        assert_eq!(distribution.iter().sum::<usize>(), 4);
+
+        let bar = |n| "#".repeat(n);
+        assert_eq!(icon_cache.hash_distribution_histogram(),
+                   format!("0 | {} 7\n1 | {} 4\n", bar(40), bar(22)));
+    }
+
+    #[test]
+    fn test_cache_list_directories() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.list_directories(), vec!["apps/16", "apps/32", "apps/48", "apps/scalable"]);
+
+        let mut dirs = icon_cache.directories();
+        assert_eq!(dirs.next(), Some("apps/16"));
+        assert_eq!(dirs.last(), Some("apps/scalable"));
+        assert_eq!(icon_cache.get_directory_by_index(2), Some("apps/48"));
+        assert_eq!(icon_cache.get_directory_by_index(4), None);
+    }
+
+    #[test]
+    fn test_cache_dir_names_borrowed() {
+        let data: &'static [u8] = include_bytes!("../test/caches/test1.cache");
+        let icon_cache = GtkIconCache::with_static_bytes(data).unwrap();
+        let range = data.as_ptr_range();
+
+        assert_eq!(icon_cache.dir_offsets.len(), 4);
+        for dir in icon_cache.lookup("test").unwrap() {
+            assert!(range.contains(&dir.as_ptr()));
+        }
+    }
+
+    #[test]
+    fn test_cache_lookup_with_flags() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let entries = icon_cache.lookup_with_flags("deepin-deb-installer").unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|e| e.flags == ImageTypeFlags::SVG));
+
+        let entries = icon_cache.lookup_with_flags("test").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.flags == ImageTypeFlags::PNG));
+    }
+
+    #[test]
+    fn test_cache_lookup_flags() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.image_lists.len(), 4);
+        assert_eq!(icon_cache.entries.len(), 4);
+
+        let entries = icon_cache.lookup_flags("deepin-deb-installer").unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|x| x.1 & ICON_TYPE_SVG != 0 && x.1 & ICON_TYPE_PNG == 0));
+
+        let entries = icon_cache.lookup_flags("test").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|x| x.1 == ICON_TYPE_PNG));
+        assert_eq!(icon_cache.lookup_flags("not-exist"), None);
+    }
+
+    #[test]
+    fn test_cache_iter() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let mut n_icons = 0;
+        for (name, dirs) in &icon_cache {
+            let mut expected = icon_cache.lookup_owned(&name).unwrap();
+            let mut dirs = dirs;
+            expected.sort();
+            dirs.sort();
+            assert_eq!(dirs, expected);
+
+            n_icons += 1;
+        }
+
+        assert_eq!(n_icons, 4);
+    }
+
+    #[test]
+    fn test_cache_to_hashmap() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let map = icon_cache.to_hashmap();
+        assert_eq!(map.len(), 4);
+        for (name, dirs) in &map {
+            assert_eq!(Some(dirs), icon_cache.lookup_owned(name).as_ref());
+        }
+        assert_eq!(map["test-48x"], vec!["apps/48"]);
+    }
+
+    #[test]
+    fn test_cache_chain_cycle() {
+        // make the entry chain to itself
+        let mut data = firefox_cache_data();
+        let bucket_index = corrupt_chain_link(&mut data, "firefox", |x| x);
+
+        check_corrupted_chain(data, bucket_index);
+    }
+
+    #[test]
+    fn test_cache_chain_out_of_bounds() {
+        // make the entry chain past the end of data instead of ending it
+        let mut data = firefox_cache_data();
+        let len = data.len();
+        let bucket_index = corrupt_chain_link(&mut data, "firefox", |_| len);
+
+        check_corrupted_chain(data, bucket_index);
+    }
+
+    #[test]
+    fn test_cache_validate() {
+        let path = "test/caches/icon-theme.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+        assert!(icon_cache.validate().is_ok());
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.add_icon("bad-index", &[(7, ImageTypeFlags::PNG)]);
+
+        let mut data = builder.build();
+        let pos = data.windows(7).position(|x| x == b"firefox").unwrap();
+        data[pos] = 0xff;
+
+        let errors = GtkIconCache::with_bytes_owned(data).unwrap().validate().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|e| matches!(*e, CacheError::InvalidUtf8 { offset } if offset == pos)));
+        assert!(errors.iter().any(|e| matches!(*e, CacheError::InvalidDirectoryIndex { index: 7 })));
+    }
+
+    #[test]
+    fn test_cache_sanitize() {
+        let path = "test/caches/icon-theme.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+        assert_eq!(icon_cache.sanitize().unwrap().to_hashmap(), icon_cache.to_hashmap());
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_bad = builder.add_directory("apps/bad");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG)]);
+        builder.add_icon("term", &[(apps_16, ImageTypeFlags::PNG), (7, ImageTypeFlags::PNG)]);
+        builder.add_icon("bad-index", &[(7, ImageTypeFlags::PNG)]);
+        builder.add_icon("bad-dir", &[(apps_bad, ImageTypeFlags::SVG)]);
+
+        let mut data = builder.build();
+        let pos = data.windows(7).position(|x| x == b"firefox").unwrap();
+        data[pos] = 0xff;
+        let pos = data.windows(8).position(|x| x == b"apps/bad").unwrap();
+        data[pos] = 0xff;
+
+        let sanitized = GtkIconCache::with_bytes_owned(data).unwrap().sanitize().unwrap();
+        assert!(sanitized.validate().is_ok());
+        assert_eq!(sanitized.list_all_icons(), vec!["term"]);
+        assert_eq!(sanitized.list_directories(), vec!["apps/16"]);
+        assert_eq!(sanitized.lookup_with_flags("term").unwrap()[0].flags, ImageTypeFlags::PNG);
+    }
+
+    #[test]
+    fn test_cache_non_utf8_directory() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_bad = builder.add_directory("apps/bad");
+        let apps_32 = builder.add_directory("apps/32");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG), (apps_32, ImageTypeFlags::PNG)]);
+        builder.add_icon("term", &[(apps_bad, ImageTypeFlags::SVG)]);
+
+        let mut data = builder.build();
+        let pos = data.windows(8).position(|x| x == b"apps/bad").unwrap();
+        data[pos + 5] = 0xff;
+
+        // the directory stays listed at its index
+        let icon_cache = GtkIconCache::with_bytes_owned(data).unwrap();
+        assert_eq!(icon_cache.list_directories(), vec!["apps/16", "apps/\u{fffd}ad", "apps/32"]);
+        assert_eq!(icon_cache.get_directory_by_index(1), Some("apps/\u{fffd}ad"));
+
+        assert!(icon_cache.contains("term"));
+        assert_eq!(icon_cache.lookup("term").unwrap(), vec!["apps/\u{fffd}ad"]);
+        assert_eq!(icon_cache.lookup("firefox").unwrap(), vec!["apps/16", "apps/32"]);
+    }
+
+    #[test]
+    fn test_cache_reload() {
+        let path = ::std::env::temp_dir().join(format!("gtk-icon-cache-reload-{}.cache", ::std::process::id()));
+        ::std::fs::copy("test/caches/test1.cache", &path).unwrap();
+
+        let mut icon_cache = GtkIconCache::with_file_path(&path).unwrap();
+        assert!(icon_cache.contains("test"));
+
+        ::std::fs::remove_file(&path).unwrap();
+        ::std::fs::copy("test/caches/icon-theme.cache", &path).unwrap();
+        icon_cache.reload().unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert!(!icon_cache.contains("test"));
+        assert!(icon_cache.contains("firefox"));
+
+        let mut icon_cache = GtkIconCache::with_bytes(include_bytes!("../test/caches/test1.cache")).unwrap();
+        assert!(matches!(icon_cache.reload(), Err(CacheError::NoSourcePath)));
+    }
+
+    #[test]
+    fn test_cache_reload_preloaded() {
+        let path = ::std::env::temp_dir().join(format!("gtk-icon-cache-reload-preloaded-{}.cache", ::std::process::id()));
+        ::std::fs::copy("test/caches/test1.cache", &path).unwrap();
+
+        let mut icon_cache = GtkIconCache::with_file_path(&path).unwrap();
+        icon_cache.reload().unwrap();
+        assert!(icon_cache.preloaded.is_empty());
+
+        icon_cache.preload_all();
+        assert!(icon_cache.preloaded.contains_key("test"));
+
+        ::std::fs::remove_file(&path).unwrap();
+        ::std::fs::copy("test/caches/icon-theme.cache", &path).unwrap();
+        icon_cache.reload().unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        // names of the new file are preloaded
+        assert!(!icon_cache.preloaded.contains_key("test"));
+        assert_eq!(icon_cache.preloaded.len(), icon_cache.list_all_icons().len());
+        assert_eq!(icon_cache.lookup("firefox"), GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap().lookup("firefox"));
+    }
+
+    #[test]
+    fn test_cache_last_modified() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert!(icon_cache.last_modified().is_some());
+        assert!(!icon_cache.is_stale_compared_to(path).unwrap());
+        assert!(!icon_cache.is_stale(path).unwrap());
+        assert!(icon_cache.is_stale_compared_to("test/caches/not-exist").is_err());
+
+        let icon_cache = GtkIconCache::with_bytes(include_bytes!("../test/caches/test1.cache")).unwrap();
+        assert!(icon_cache.last_modified().is_none());
+        assert!(icon_cache.is_stale_compared_to(path).unwrap());
+        assert!(icon_cache.is_stale("test/caches").unwrap());
+
+        // the theme directory is not read without a modification time
+        assert!(icon_cache.is_stale("test/caches/not-exist").unwrap());
+    }
+
+    #[test]
+    fn test_cache_lookup_in_directory() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert!(icon_cache.lookup_in_directory("test", "apps/32"));
+        assert!(!icon_cache.lookup_in_directory("test", "apps/16"));
+        assert!(!icon_cache.lookup_in_directory("not-exist", "apps/32"));
+
+        let preferred = ["apps/16", "apps/48", "apps/32"];
+        assert_eq!(icon_cache.lookup_preferred_directory("test", &preferred).unwrap(), "apps/48");
+        assert_eq!(icon_cache.lookup_preferred_directory("test", &["apps/16"]), None);
+    }
+
+    #[test]
+    fn test_cache_eq() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache, GtkIconCache::with_file_path(path).unwrap());
+        assert_eq!(icon_cache, GtkIconCache::with_bytes(include_bytes!("../test/caches/test1.cache")).unwrap());
+        assert_ne!(icon_cache, GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap());
+    }
+
+    #[test]
+    fn test_cache_hash() {
+        let path = "test/caches/test1.cache";
+
+        let mut caches = HashSet::new();
+        assert!(caches.insert(GtkIconCache::with_file_path(path).unwrap()));
+        assert!(!caches.insert(GtkIconCache::with_bytes(include_bytes!("../test/caches/test1.cache")).unwrap()));
+        assert!(caches.insert(GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap()));
+        assert_eq!(caches.len(), 2);
+    }
+
+    #[test]
+    fn test_cache_lookup_many() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let names = ["test", "deepin-deb-installer", "not-exist", "test"];
+        let r = icon_cache.lookup_many(&names);
+
+        assert_eq!(r.len(), 3);
+        assert_eq!(r["test"].as_ref().unwrap().len(), 2);
+        assert_eq!(r["deepin-deb-installer"].as_ref().unwrap().len(), 4);
+        assert_eq!(r["not-exist"], None);
+    }
+
+    #[test]
+    fn test_cache_icons_in_directory() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let mut icons = icon_cache.icons_in_directory("apps/32");
+        icons.sort();
+        assert_eq!(icons, vec!["deepin-deb-installer", "test"]);
+        assert!(icon_cache.icons_in_directory("apps/64").is_empty());
+    }
+
+    #[test]
+    fn test_cache_lookup_all_dirs() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+        assert_eq!(icon_cache.lookup_all_dirs(), icon_cache.list_directories());
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        builder.add_directory("empty");
+        let apps_48 = builder.add_directory("apps/48");
+        builder.add_icon("firefox", &[(apps_48, ImageTypeFlags::PNG), (apps_16, ImageTypeFlags::PNG), (7, ImageTypeFlags::PNG)]);
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        assert_eq!(icon_cache.lookup_all_dirs(), vec!["apps/16", "apps/48"]);
+    }
+
+    #[test]
+    fn test_cache_count_icons_per_directory() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let counts = icon_cache.count_icons_per_directory();
+        assert_eq!(counts.len(), 4);
+        for (dir, &n) in &counts {
+            assert_eq!(n, icon_cache.icons_in_directory(dir).len());
+        }
+        assert_eq!(counts["apps/32"], 2);
+
+        let mut builder = GtkIconCacheBuilder::new();
+        builder.add_directory("empty");
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+        assert_eq!(icon_cache.count_icons_per_directory()["empty"], 0);
+    }
+
+    #[test]
+    fn test_cache_display() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+        assert_eq!(icon_cache.to_string(),
+                   "GtkIconCache { version: 1.0, buckets: 11, directories: 4, source: test/caches/test1.cache }");
+
+        let icon_cache = GtkIconCache::with_bytes(include_bytes!("../test/caches/test1.cache")).unwrap();
+        assert_eq!(icon_cache.to_string(), "GtkIconCache { version: 1.0, buckets: 11, directories: 4 }");
+    }
+
+    #[test]
+    fn test_cache_lookup_multi_dir() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.lookup_multi_dir("test", &["apps/64", "apps/48", "apps/32"]), Some("apps/48"));
+        let first = icon_cache.lookup("test").unwrap()[0];
+        assert_eq!(icon_cache.lookup_multi_dir("test", &["apps/64"]), Some(first));
+        assert_eq!(icon_cache.lookup_multi_dir("test", &[]), Some(first));
+        assert_eq!(icon_cache.lookup_multi_dir("not-exist", &["apps/48"]), None);
+    }
+
+    #[test]
+    fn test_cache_lookup_for_size() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(icon_cache.lookup_for_size("deepin-deb-installer", 40), Some("apps/32"));
+        assert_eq!(icon_cache.lookup_for_size("deepin-deb-installer", 48), Some("apps/48"));
+        assert_eq!(icon_cache.lookup_for_size("deepin-deb-installer", 128), Some("apps/48"));
+        assert_eq!(icon_cache.lookup_for_size("deepin-deb-installer", 8), Some("apps/16"));
+        assert_eq!(icon_cache.lookup_for_size("test", 16), Some("apps/32"));
+        assert_eq!(icon_cache.lookup_for_size("not-exist", 16), None);
+    }
+
+    #[test]
+    fn test_cache_lookup_scalable_preferred() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let dirs = icon_cache.lookup_scalable_preferred("deepin-deb-installer").unwrap();
+        assert_eq!(dirs, vec!["apps/scalable", "apps/48", "apps/32", "apps/16"]);
+        assert_eq!(icon_cache.lookup_scalable_preferred("test").unwrap(), vec!["apps/48", "apps/32"]);
+        assert_eq!(icon_cache.lookup_scalable_preferred("not-exist"), None);
+    }
+
+    #[test]
+    fn test_cache_lookup_with_size_hint() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let dirs = icon_cache.lookup_with_size_hint("deepin-deb-installer", 40).unwrap();
+        assert_eq!(dirs, vec!["apps/48", "apps/32", "apps/16", "apps/scalable"]);
+        let dirs = icon_cache.lookup_with_size_hint("deepin-deb-installer", 8).unwrap();
+        assert_eq!(dirs, vec!["apps/16", "apps/32", "apps/48", "apps/scalable"]);
+        assert_eq!(icon_cache.lookup_with_size_hint("not-exist", 16), None);
+
+        let mut builder = GtkIconCacheBuilder::new();
+        let dirs: Vec<_> = ["apps", "apps/scalable", "apps/48", "apps/48@2x", "apps/64"].iter()
+            .map(|x| (builder.add_directory(x), ImageTypeFlags::PNG))
+            .collect();
+        builder.add_icon("firefox", &dirs);
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        let dirs = icon_cache.lookup_with_size_hint("firefox", 96).unwrap();
+        assert_eq!(dirs, vec!["apps/48@2x", "apps/64", "apps/48", "apps/scalable", "apps"]);
+    }
+
+    #[test]
+    fn test_cache_lookup_icon_file_path() {
+        let theme_dir = ::std::env::temp_dir().join(format!("gtk-icon-cache-file-path-{}", ::std::process::id()));
+        for dir in &["apps/16", "apps/48"] {
+            ::std::fs::create_dir_all(theme_dir.join(dir)).unwrap();
+        }
+        for file in &["apps/16/firefox.png", "apps/48/firefox.png", "apps/48/firefox.svg", "apps/48/term.xpm"] {
+            ::std::fs::write(theme_dir.join(file), b"").unwrap();
+        }
+
+        let icon_cache = build_cache(&[
+            ("firefox", &[("apps/48", ImageTypeFlags::PNG | ImageTypeFlags::SVG), ("apps/16", ImageTypeFlags::PNG)]),
+            ("term", &[("apps/48", ImageTypeFlags::XPM)]),
+            ("editor", &[("apps/16", ImageTypeFlags::PNG)]),
+        ]);
+
+        let firefox = icon_cache.lookup_icon_file_path("firefox", &theme_dir);
+        let term = icon_cache.lookup_icon_file_path("term", &theme_dir);
+        let editor = icon_cache.lookup_icon_file_path("editor", &theme_dir);
+        let missing = icon_cache.lookup_icon_file_path("not-exist", &theme_dir);
+        ::std::fs::remove_dir_all(&theme_dir).unwrap();
+
+        assert_eq!(firefox, Some(theme_dir.join("apps/48/firefox.svg")));
+        assert_eq!(term, Some(theme_dir.join("apps/48/term.xpm")));
+        assert_eq!(editor, None);
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn test_dir_size() {
+        assert_eq!(dir_size("apps/48"), Some(48));
+        assert_eq!(dir_size("48x48/apps"), Some(48));
+        assert_eq!(dir_size("apps/scalable"), Some(u32::MAX));
+        assert_eq!(dir_size("48x32/apps"), None);
+        assert_eq!(dir_size("apps"), None);
+        assert_eq!(dir_size("48x48@2x/apps"), Some(96));
+        assert_eq!(dir_size("apps/24@3x"), Some(72));
+        assert_eq!(dir_size("48x48@2/apps"), None);
+    }
+
+    #[test]
+    fn test_cache_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<GtkIconCache>();
+        assert_send_sync::<GtkIconCacheSet>();
+    }
+
+    #[test]
+    fn test_cache_lookup_prefix() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let mut icons = icon_cache.lookup_prefix("test");
+        icons.sort();
+        assert_eq!(icons.len(), 2);
+        assert_eq!(icons[0].0, "test");
+        assert_eq!(icons[0].1.len(), 2);
+        assert_eq!(icons[1].0, "test-48x");
+
+        assert_eq!(icon_cache.lookup_prefix("").len(), 4);
+        assert!(icon_cache.lookup_prefix("not-exist").is_empty());
+    }
+
+    #[test]
+    fn test_cache_try_from() {
+        let path = "test/caches/test1.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        assert_eq!(GtkIconCache::try_from(Path::new(path)).unwrap(), icon_cache);
+        assert_eq!(GtkIconCache::try_from(PathBuf::from(path)).unwrap(), icon_cache);
+        assert!(GtkIconCache::try_from(Path::new("test/caches/not-exist.cache")).is_err());
+    }
+
+    #[test]
+    fn test_cache_lookup_with_dir_indices() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_48 = builder.add_directory("apps/48");
+        builder.add_icon("firefox", &[(apps_48, ImageTypeFlags::PNG), (apps_16, ImageTypeFlags::PNG), (7, ImageTypeFlags::PNG)]);
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        assert_eq!(icon_cache.lookup_with_dir_indices("firefox").unwrap(), vec![1, 0, 7]);
+        assert_eq!(icon_cache.get_directory_by_index(1), Some("apps/48"));
+        assert_eq!(icon_cache.lookup_with_dir_indices("not-exist"), None);
+    }
+
+    #[test]
+    fn test_cache_entry_size_bytes() {
+        let icon_cache = build_cache(&[("firefox", &[("apps/16", ImageTypeFlags::PNG), ("apps/48", ImageTypeFlags::PNG)])]);
+
+        assert_eq!(icon_cache.entry_size_bytes("firefox"), Some(12 + 8 + 4 + 16));
+        assert_eq!(icon_cache.entry_size_bytes("not-exist"), None);
+    }
+
+    #[test]
+    fn test_cache_lookup_all_with_flags() {
+        let icon_cache = build_cache(&[("firefox", &[("apps/16", ImageTypeFlags::PNG), ("apps/16", ImageTypeFlags::XPM)])]);
+
+        let entries = icon_cache.lookup_all_with_flags("firefox").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].flags, ImageTypeFlags::PNG);
+        assert_eq!(entries[1].flags, ImageTypeFlags::XPM);
+
+        let entries = icon_cache.lookup_with_flags("firefox").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].dir, "apps/16");
+        assert_eq!(entries[0].flags, ImageTypeFlags::PNG | ImageTypeFlags::XPM);
+    }
+
+    #[test]
+    fn test_cache_with_file() {
+        let path = "test/caches/test1.cache";
+        let mut icon_cache = GtkIconCache::with_file(::std::fs::File::open(path).unwrap()).unwrap();
+
+        assert_eq!(icon_cache, GtkIconCache::with_file_path(path).unwrap());
+        assert!(icon_cache.last_modified().is_some());
+        assert!(matches!(icon_cache.reload(), Err(CacheError::NoSourcePath)));
+    }
+
+    #[test]
+    fn test_cache_lookup_image_type() {
+        let icon_cache = build_cache(&[("firefox", &[("apps/16", ImageTypeFlags::PNG), ("apps/scalable", ImageTypeFlags::SVG)])]);
+
+        assert_eq!(icon_cache.lookup_svg_only("firefox").unwrap(), vec!["apps/scalable"]);
+        assert_eq!(icon_cache.lookup_png_only("firefox").unwrap(), vec!["apps/16"]);
+        assert!(icon_cache.lookup_svg_only("not-exist").is_none());
+    }
+
+    #[test]
+    fn test_cache_lookup_with_options() {
+        let icon_cache = build_cache(&[("firefox", &[("apps/16", ImageTypeFlags::PNG),
+                                                     ("apps/32", ImageTypeFlags::PNG),
+                                                     ("apps/scalable", ImageTypeFlags::SVG)])]);
+
+        let opts = LookupOptions::new();
+        assert!(icon_cache.lookup_with_options("Firefox", &opts).is_none());
+        assert_eq!(icon_cache.lookup_with_options("firefox", &opts).unwrap().len(), 3);
+
+        let opts = LookupOptions::new().case_insensitive(true).prefer_svg(true).max_dirs(2);
+        let entries = icon_cache.lookup_with_options("Firefox", &opts).unwrap();
+        let dirs: Vec<_> = entries.iter().map(|x| x.dir).collect();
+        assert_eq!(dirs, vec!["apps/scalable", "apps/16"]);
+
+        // same fallback as `lookup_case_insensitive`
+        let icon_cache = build_cache(&[("TERM", &[("apps/48", ImageTypeFlags::PNG)])]);
+
+        let opts = LookupOptions::new().case_insensitive(true);
+        assert_eq!(icon_cache.lookup_with_options("Term", &opts).unwrap()[0].dir, "apps/48");
+        assert_eq!(icon_cache.lookup_case_insensitive("Term").unwrap(), vec!["apps/48"]);
+    }
+
+    #[test]
+    fn test_cache_lookup_bytes() {
+        let mut data = build_cache_data(&[("caf\u{e9}", &[("apps/16", ImageTypeFlags::PNG)])]);
+
+        // replace the UTF-8 `é` with latin-1 `é` and a padding NUL
+        let pos = data.windows(5).position(|x| x == "caf\u{e9}".as_bytes()).unwrap();
+        data[pos + 3] = 0xe9;
+        data[pos + 4] = 0;
+
+        // move the entry to the bucket of its new name
+        let (old_link, new_link, entry) = {
+            let icon_cache = GtkIconCache::with_bytes(&data).unwrap();
+            let old_link = icon_cache.hash_offset + 4 + icon_cache.bucket_index("caf\u{e9}".as_bytes()).unwrap() * 4;
+            let new_link = icon_cache.hash_offset + 4 + icon_cache.bucket_index(b"caf\xe9").unwrap() * 4;
+
+            (old_link, new_link, icon_cache.read_card32_from(old_link).unwrap())
+        };
+        set_card32(&mut data, old_link, EMPTY_OFFSET);
+        set_card32(&mut data, new_link, entry);
+
+        let icon_cache = GtkIconCache::with_bytes_owned(data).unwrap();
+        assert_eq!(icon_cache.lookup_bytes(b"caf\xe9").unwrap(), vec!["apps/16"]);
+        assert!(icon_cache.lookup("caf\u{e9}").is_none());
+        assert!(icon_cache.lookup("caf\u{fffd}").is_none());
+    }
+
+    #[test]
+    fn test_cache_with_bytes() {
+        let icon_cache = GtkIconCache::with_bytes(include_bytes!("../test/caches/icon-theme.cache")).unwrap();
+
+        let dirs = icon_cache.lookup("firefox").unwrap();
+        assert!(dirs.contains(&"apps/64"));
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_cache_with_file_path_async() {
+        let rt = ::tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let path = "test/caches/test1.cache";
+
+        let icon_cache = rt.block_on(GtkIconCache::with_file_path_async(path)).unwrap();
+        assert_eq!(icon_cache, GtkIconCache::with_file_path(path).unwrap());
+        assert!(icon_cache.last_modified().is_some());
+        assert!(rt.block_on(GtkIconCache::with_file_path_async("not-exist.cache")).is_err());
+    }
+
+    #[test]
+    fn test_cache_with_reader() {
+        let data = include_bytes!("../test/caches/test1.cache");
+        let icon_cache = GtkIconCache::with_reader(&data[..]).unwrap();
+        assert_eq!(icon_cache, GtkIconCache::with_bytes(data).unwrap());
+
+        // data split over several reads
+        let (head, tail) = data.split_at(100);
+        assert_eq!(GtkIconCache::with_reader(head.chain(tail)).unwrap(), icon_cache);
+
+        match GtkIconCache::with_reader(&data[..2]) {
+            Err(CacheError::TruncatedData { offset: 2 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_with_static_bytes() {
+        let icon_cache = GtkIconCache::with_static_bytes(include_bytes!("../test/caches/test1.cache")).unwrap();
+
+        assert_eq!(icon_cache, GtkIconCache::with_file_path("test/caches/test1.cache").unwrap());
+    }
+
+    #[test]
+    fn test_cache_from_slice_unchecked() {
+        let data = include_bytes!("../test/caches/test1.cache");
+        let icon_cache = GtkIconCache::with_static_bytes(data).unwrap();
+        let unchecked = unsafe { GtkIconCache::from_slice_unchecked(data) };
+
+        assert_eq!(unchecked.version(), (1, 0));
+        assert_eq!(unchecked.n_buckets(), icon_cache.n_buckets());
+        assert_eq!(unchecked.icon_count(), icon_cache.icon_count());
+        assert_eq!(unchecked.list_directories(), icon_cache.list_directories());
+        for name in &["test", "test-48x", "name.with.dot", "not-exist", ""] {
+            assert_eq!(unchecked.lookup(name), icon_cache.lookup(name));
+        }
+
+        // the header is not checked
+        let mut data = data.to_vec();
+        data[1] = 99;
+        let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+        assert!(GtkIconCache::with_static_bytes(data).is_err());
+
+        let unchecked = unsafe { GtkIconCache::from_slice_unchecked(data) };
+        assert_eq!(unchecked.version(), (1, 0));
+        assert!(unchecked.lookup("test").is_some());
+    }
+
+    #[test]
+    fn test_cache_wrong_version() {
+        let mut data = include_bytes!("../test/caches/icon-theme.cache").to_vec();
+
+        // major version 99, minor version 0
+        data[0] = 0;
+        data[1] = 99;
+        match GtkIconCache::with_bytes(&data) {
+            Err(CacheError::InvalidVersion { major: 99, minor: 0 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_not_found() {
+        match GtkIconCache::with_file_path("test/caches/not-exist.cache") {
+            Err(CacheError::Io(_)) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_from_theme_dir() {
+        let icon_cache = GtkIconCache::from_theme_dir("test/caches").unwrap();
+        assert_eq!(icon_cache, GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap());
+
+        match GtkIconCache::from_theme_dir("src") {
+            Err(CacheError::MissingThemeCache { theme_dir }) => assert_eq!(theme_dir, Path::new("src")),
+            _ => panic!("expected MissingThemeCache"),
+        }
+
+        match GtkIconCache::from_theme_dir("not-exist") {
+            Err(CacheError::Io(_)) => {},
+            _ => panic!("expected Io error"),
+        }
+    }
+
+    #[test]
+    fn test_detect_format_version() {
+        assert_eq!(detect_format_version("test/caches/icon-theme.cache").unwrap(), (1, 0));
+        assert!(detect_format_version("test/caches/not-exist.cache").is_err());
+
+        let path = ::std::env::temp_dir().join(format!("gtk-icon-cache-version-{}", ::std::process::id()));
+        ::std::fs::write(&path, [0, 2, 0]).unwrap();
+        let truncated = detect_format_version(&path);
+        ::std::fs::write(&path, [0, 2, 0, 1, 0]).unwrap();
+        let unsupported = detect_format_version(&path);
+        ::std::fs::remove_file(&path).unwrap();
+
+        match truncated {
+            Err(CacheError::TruncatedData { offset: 2 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(unsupported.unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn test_cache_truncated_header() {
+        match GtkIconCache::with_bytes(&[0, 1, 0, 0, 0, 0]) {
+            Err(CacheError::TruncatedData { offset: 4 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_little_endian() {
+        let data = [
+            1, 0, 0, 0,
+            12, 0, 0, 0,                // hash offset
+            48, 0, 0, 0,                // directory list offset
+            1, 0, 0, 0,                 // n_buckets
+            20, 0, 0, 0,                // bucket 0
+            0xff, 0xff, 0xff, 0xff,     // entry: next
+            32, 0, 0, 0,                // name offset
+            36, 0, 0, 0,                // image list offset
+            b'f', b'x', 0, 0,
+            1, 0, 0, 0,                 // n_images
+            0, 0, 4, 0,                 // directory index, flags
+            0, 0, 0, 0,                 // image data offset
+            1, 0, 0, 0,                 // n_directories
+            56, 0, 0, 0,
+            b'a', b'p', b'p', 0,
+        ];
+
+        match GtkIconCache::with_bytes(&data) {
+            Err(CacheError::InvalidVersion { major: 256, minor: 0 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let opts = LoadOptions::new().byte_order(ByteOrder::LittleEndian);
+        let icon_cache = GtkIconCache::with_bytes_and_options(&data, &opts).unwrap();
+
+        assert_eq!(icon_cache.version(), (1, 0));
+        assert_eq!(icon_cache.list_directories(), vec!["app"]);
+        assert_eq!(icon_cache.lookup("fx").unwrap(), vec!["app"]);
+        assert_eq!(icon_cache.lookup_with_flags("fx").unwrap()[0].flags, ImageTypeFlags::PNG);
+        assert!(icon_cache.validate().is_ok());
+    }
+
+    #[test]
+    fn test_cache_max_chain_length() {
+        let path = "test/caches/test1.cache";
+        let opts = LoadOptions::new().max_chain_length(1);
+        let mut icon_cache = GtkIconCache::with_file_path_and_options(path, &opts).unwrap();
+        assert!(icon_cache.reload().is_ok());
+
+        icon_cache.load_options = LoadOptions::new().max_chain_length(0);
+        match icon_cache.reload() {
+            Err(CacheError::ChainTooLong { length: 1, .. }) => {},
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_chain_length_unchecked_by_default() {
+        let mut builder = GtkIconCacheBuilder::with_hash_fn(|_| 0);
+        let apps_16 = builder.add_directory("apps/16");
+        for i in 0..3 {
+            builder.add_icon(&format!("icon-{}", i), &[(apps_16, ImageTypeFlags::PNG)]);
+        }
+
+        // all icons in the first and only bucket
+        let mut data = builder.build();
+        data[12..16].copy_from_slice(&[0, 0, 0, 1]);
+
+        let icon_cache = GtkIconCache::with_bytes(&data).unwrap();
+        assert_eq!(icon_cache.n_buckets(), 1);
+        assert_eq!(icon_cache.icon_count(), 3);
+
+        let opts = LoadOptions::new().max_chain_length(2);
+        match GtkIconCache::with_bytes_and_options(&data, &opts) {
+            Err(CacheError::ChainTooLong { bucket: 0, length: 3 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_zero_buckets() {
+        let data = [
+            0, 1, 0, 0,
+            0, 0, 0, 12,    // hash offset
+            0, 0, 0, 16,    // directory list offset
+            0, 0, 0, 0,     // n_buckets
+            0, 0, 0, 0,     // n_directories
+        ];
+
+        match GtkIconCache::with_bytes(&data) {
+            Err(CacheError::CorruptHashTable) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let icon_cache = raw_cache(data.to_vec());
+        assert!(icon_cache.lookup("firefox").is_none());
+        assert!(icon_cache.lookup_many(&["firefox"])["firefox"].is_none());
+    }
+
+    #[test]
+    fn test_cache_empty_directory_list() {
+        let data = [
+            0, 1, 0, 0,
+            0, 0, 0, 12,                // hash offset
+            0, 0, 0, 48,                // directory list offset
+            0, 0, 0, 1,                 // n_buckets
+            0, 0, 0, 20,                // bucket 0
+            0xff, 0xff, 0xff, 0xff,     // entry: next
+            0, 0, 0, 32,                // name offset
+            0, 0, 0, 36,                // image list offset
+            b'f', b'x', 0, 0,
+            0, 0, 0, 1,                 // n_images
+            0, 0, 0, 4,                 // directory index, flags
+            0, 0, 0, 0,                 // image data offset
+            0, 0, 0, 0,                 // n_directories
+        ];
+
+        let icon_cache = GtkIconCache::with_bytes(&data).unwrap();
+
+        assert!(icon_cache.list_directories().is_empty());
+        assert!(icon_cache.lookup("fx").is_none());
+        assert!(IconCache::lookup(&GtkIconCacheRef::with_bytes(&data).unwrap(), "fx").is_none());
+        assert!(icon_cache.lookup("firefox").is_none());
+        assert!(icon_cache.lookup_preferred_directory("fx", &["app"]).is_none());
+        assert!(icon_cache.lookup_multi_dir("fx", &["app"]).is_none());
+        assert!(icon_cache.lookup_for_size("fx", 48).is_none());
+        assert!(icon_cache.count_icons_per_directory().is_empty());
+        assert!(icon_cache.lookup_all_dirs().is_empty());
+        assert_eq!(icon_cache.directories().count(), 0);
+        match icon_cache.validate().as_ref().map_err(|x| x.as_slice()) {
+            Err([CacheError::InvalidDirectoryIndex { index: 0 }]) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_overlapping_tables() {
+        let mut data = vec![
+            0, 1, 0, 0,
+            0, 0, 0, 12,    // hash offset
+            0, 0, 0, 16,    // directory list offset, the first bucket
+            0, 0, 0, 1,     // n_buckets
+            0, 0, 0, 0,     // bucket 0, also n_directories
+        ];
+
+        match GtkIconCache::with_bytes(&data) {
+            Err(CacheError::OverlappingTables { hash_offset: 12, directory_list_offset: 16 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        // both tables at the same offset
+        data[11] = 12;
+        match GtkIconCache::with_bytes(&data) {
+            Err(CacheError::OverlappingTables { hash_offset: 12, directory_list_offset: 12 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_invalid_header_offset() {
+        let mut data = include_bytes!("../test/caches/test1.cache").to_vec();
+
+        // directory list offset points past the end
+        data[8..12].copy_from_slice(&[0, 0, 0x10, 0]);
+        match GtkIconCache::with_bytes(&data) {
+            Err(CacheError::InvalidOffset { offset: 0x1000 }) => {},
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_cache_truncated_string() {
+        // the last directory name is cut off before its terminator
+        let path = "test/caches/truncated-string.cache";
+        let icon_cache = GtkIconCache::with_file_path(path).unwrap();
+
+        let dirs = icon_cache.lookup("test").unwrap();
+        assert!(dirs.contains(&"apps/32"));
+        assert!(dirs.contains(&"apps/48"));
+    }
+
+    #[test]
+    fn test_read_card16() {
+        let cache = raw_cache(vec![0x01, 0x02]);
+        assert_eq!(cache.read_card16_from(0), Some(0x0102));
+        assert_eq!(cache.read_card16_from(1), None);
+
+        let cache = raw_cache(vec![0x01]);
+        assert_eq!(cache.read_card16_from(0), None);
+        assert_eq!(cache.read_card16_from(1), None);
+    }
+
+    #[test]
+    fn test_cstring_matches() {
+        let cache = raw_cache(b"test\0test-48x".to_vec());
+
+        assert!(cache.cstring_matches(0, b"test"));
+        assert!(!cache.cstring_matches(0, b"tes"));
+        assert!(!cache.cstring_matches(0, b"test\0"));
+        assert!(!cache.cstring_matches(5, b"test"));
+        assert!(!cache.cstring_matches(5, b"test-48x"));
+        assert!(!cache.cstring_matches(4, b""));
+    }
+
+    #[test]
+    fn test_read_cstring_too_long() {
+        let mut data = vec![b'a'; MAX_NAME_LEN + 10];
+        data.push(0);
+        let icon_cache = raw_cache(data);
+
+        assert_eq!(icon_cache.read_cstring_from(0), None);
+        assert_eq!(icon_cache.read_cstring_from(10).map(|x| x.len()), Some(MAX_NAME_LEN));
+    }
+
+    #[test]
+    fn test_read_card32() {
+        let cache = raw_cache(vec![0x01, 0x02, 0x03, 0x04]);
+
+        assert_eq!(cache.read_card32_from(0), Some(0x01020304));
+        assert_eq!(cache.read_card32_from(1), None);
+    }
+
+    #[test]
+    #[cfg(feature = "raw-access")]
+    fn test_as_raw_bytes() {
+        let data = std::fs::read("test/caches/test1.cache").unwrap();
+        let icon_cache = GtkIconCache::with_bytes(&data).unwrap();
+
+        assert_eq!(icon_cache.as_raw_bytes(), &data[..]);
+    }
+
+    #[test]
+    fn test_icon_name_hash() {
+        assert_eq!(icon_name_hash("deepin-deb-installer"), 1927089920);
+        assert_eq!(icon_name_bytes_hash(b"\xe4"), 0xffff_ffe4);
+    }
+
+    #[test]
+    fn test_icon_name_hash_non_ascii() {
+        // written by gtk-update-icon-cache, whose validator then rejects the
+        // non-ASCII names, kept from its temporary `.icon-theme.cache`
+        let icon_cache = GtkIconCache::with_file_path("test/caches/non-ascii.cache").unwrap();
+
+        let mut buckets = HashMap::new();
+        icon_cache.walk_buckets(|bucket_index, entry_offset| {
+            let name = icon_cache.read_card32_from(entry_offset + 4).and_then(|x| icon_cache.read_cstring_from(x)).unwrap();
+            buckets.insert(name, bucket_index);
+        });
+
+        // bytes above 0x7f are hashed as signed chars, like GTK does
+        for name in &["caf\u{e9}", "\u{56fe}\u{6807}", "\u{c4}rger", "firefox"] {
+            assert_eq!(buckets[*name], icon_name_hash(name) as usize % icon_cache.n_buckets());
+            assert!(icon_cache.contains(name));
+        }
+
+        assert_eq!(icon_cache.lookup("caf\u{e9}").unwrap(), vec!["apps/16"]);
+        assert_eq!(icon_cache.lookup("\u{56fe}\u{6807}").unwrap(), vec!["apps/scalable"]);
+        assert_eq!(icon_cache.lookup("\u{c4}rger").unwrap(), vec!["apps/16"]);
     }
 }
@@ -0,0 +1,184 @@
+#[cfg(all(unix, feature = "std"))]
+use memmap2::Advice;
+
+///
+/// Options for `GtkIconCache::lookup_with_options`.
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use gtk_icon_cache::*;
+///
+/// let icon_cache = GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap();
+/// let opts = LookupOptions::new().case_insensitive(true).prefer_svg(true).max_dirs(1);
+///
+/// let entries = icon_cache.lookup_with_options("Firefox", &opts).unwrap();
+/// assert_eq!(entries.len(), 1);
+/// # }
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LookupOptions {
+    pub(crate) case_insensitive: bool,
+    pub(crate) prefer_svg: bool,
+    pub(crate) max_dirs: Option<usize>,
+}
+
+impl LookupOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Retry with the name in ASCII lower case and then upper case if the
+    /// icon is not found, like `GtkIconCache::lookup_case_insensitive`.
+    ///
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    ///
+    /// Put directories with a `.svg` file first.
+    ///
+    pub fn prefer_svg(mut self, prefer_svg: bool) -> Self {
+        self.prefer_svg = prefer_svg;
+        self
+    }
+
+    ///
+    /// Return at most `max_dirs` directories.
+    ///
+    pub fn max_dirs(mut self, max_dirs: usize) -> Self {
+        self.max_dirs = Some(max_dirs);
+        self
+    }
+}
+
+///
+/// Options for `GtkIconCache::with_file_path_and_options` and
+/// `GtkIconCache::with_bytes_and_options`.
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use gtk_icon_cache::*;
+///
+/// let opts = LoadOptions::new().max_chain_length(1);
+/// assert!(GtkIconCache::with_file_path_and_options("test/caches/icon-theme.cache", &opts).is_err());
+///
+/// let opts = LoadOptions::new().mmap_advice(MmapAdvice::Random);
+/// assert!(GtkIconCache::with_file_path_and_options("test/caches/icon-theme.cache", &opts).is_ok());
+/// # }
+/// ```
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    pub(crate) max_chain_length: Option<usize>,
+    pub(crate) mmap_advice: MmapAdvice,
+    pub(crate) byte_order: ByteOrder,
+    pub(crate) hash_fn: Option<fn(&str) -> usize>,
+}
+
+impl LoadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Reject caches with a bucket chain longer than `max_chain_length`,
+    /// chains are not limited by default.
+    ///
+    pub fn max_chain_length(mut self, max_chain_length: usize) -> Self {
+        self.max_chain_length = Some(max_chain_length);
+        self
+    }
+
+    ///
+    /// Tell the kernel how a mapped cache file will be accessed, ignored
+    /// on platforms without `madvise` and for caches not loaded from a file.
+    ///
+    pub fn mmap_advice(mut self, mmap_advice: MmapAdvice) -> Self {
+        self.mmap_advice = mmap_advice;
+        self
+    }
+
+    ///
+    /// Byte order of numbers in the cache, GTK always writes big endian
+    /// caches but other generators may not.
+    ///
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    ///
+    /// Hash of an icon name used to find its bucket, for caches built with
+    /// `GtkIconCacheBuilder::with_hash_fn`. Defaults to `icon_name_hash`.
+    ///
+    /// Names which are not valid UTF-8 are hashed after a lossy conversion.
+    ///
+    pub fn hash_fn(mut self, hash_fn: fn(&str) -> usize) -> Self {
+        self.hash_fn = Some(hash_fn);
+        self
+    }
+}
+
+///
+/// Byte order of card16 and card32 values in a cache.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// The order GTK uses.
+    #[default]
+    BigEndian,
+    LittleEndian,
+}
+
+impl ByteOrder {
+    ///
+    /// Convert a card16 read as big endian to this order.
+    ///
+    pub(crate) fn card16(self, v: u16) -> u16 {
+        match self {
+            ByteOrder::BigEndian => v,
+            ByteOrder::LittleEndian => v.swap_bytes(),
+        }
+    }
+
+    ///
+    /// Convert a card32 read as big endian to this order.
+    ///
+    pub(crate) fn card32(self, v: u32) -> u32 {
+        match self {
+            ByteOrder::BigEndian => v,
+            ByteOrder::LittleEndian => v.swap_bytes(),
+        }
+    }
+}
+
+///
+/// Expected access pattern of a mapped cache file, passed to `madvise`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MmapAdvice {
+    /// No advice, the kernel default.
+    #[default]
+    Normal,
+    /// Read once from start to end, e.g. to dump or convert the cache.
+    Sequential,
+    /// Random lookups, for long-lived caches.
+    Random,
+    /// The whole cache will be needed soon, read it ahead.
+    WillNeed,
+}
+
+#[cfg(all(unix, feature = "std"))]
+impl From<MmapAdvice> for Advice {
+    fn from(advice: MmapAdvice) -> Self {
+        match advice {
+            MmapAdvice::Normal => Advice::Normal,
+            MmapAdvice::Sequential => Advice::Sequential,
+            MmapAdvice::Random => Advice::Random,
+            MmapAdvice::WillNeed => Advice::WillNeed,
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use ByteOrder;
+use CacheError;
+use CHAIN_CHECK_THRESHOLD;
+use EMPTY_OFFSET;
+use MAX_NAME_LEN;
+
+use alloc::collections::BTreeSet;
+
+///
+/// Header fields and table sizes of a cache, checked by `Reader::header`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub hash_offset: usize,
+    pub directory_list_offset: usize,
+    pub n_buckets: usize,
+    pub n_directories: usize,
+}
+
+///
+/// Reads fields of cache data, shared by `GtkIconCache` and `GtkIconCacheRef`
+/// so both accept the same caches.
+///
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    byte_order: ByteOrder,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], byte_order: ByteOrder) -> Self {
+        Self { data, byte_order }
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn card16(&self, offset: usize) -> Option<usize> {
+        let m = self.data;
+
+        if offset + 2 <= m.len() {
+            let v = (m[offset    ] as u16) << 8 |
+                    (m[offset + 1] as u16);
+            Some(self.byte_order.card16(v) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn card32(&self, offset: usize) -> Option<usize> {
+        let m = self.data;
+
+        if offset + 4 <= m.len() {
+            let v = (m[offset    ] as u32) << 24 |
+                    (m[offset + 1] as u32) << 16 |
+                    (m[offset + 2] as u32) <<  8 |
+                    (m[offset + 3] as u32);
+            Some(self.byte_order.card32(v) as usize)
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Bytes of the nul terminated string at `offset`, `None` if it is
+    /// unterminated or longer than `MAX_NAME_LEN`.
+    ///
+    pub fn cstring(&self, offset: usize) -> Option<&'a [u8]> {
+        let m = self.data;
+        let mut terminate = offset;
+        let limit = m.len().min(offset.saturating_add(MAX_NAME_LEN + 1));
+
+        while terminate < limit && m[terminate] != b'\0' { terminate += 1; }
+
+        if terminate >= limit && limit < m.len() {
+            warn!("string at offset {} exceeds {} bytes", offset, MAX_NAME_LEN);
+            return None;
+        }
+
+        // unterminated string
+        if terminate >= m.len() { return None; }
+
+        Some(&m[offset..terminate])
+    }
+
+    ///
+    /// Read the header and check both tables fit in the data without
+    /// overlapping.
+    ///
+    pub fn header(&self) -> Result<Header, CacheError> {
+        let major_version = self.card16(0).ok_or(CacheError::TruncatedData { offset: 0 })?;
+        let minor_version = self.card16(2).ok_or(CacheError::TruncatedData { offset: 2 })?;
+
+        if major_version != 1 || minor_version != 0 {
+            return Err(CacheError::InvalidVersion {
+                major: major_version as u16,
+                minor: minor_version as u16,
+            });
+        }
+
+        let hash_offset = self.card32(4).ok_or(CacheError::TruncatedData { offset: 4 })?;
+        let directory_list_offset = self.card32(8).ok_or(CacheError::TruncatedData { offset: 8 })?;
+
+        // both tables start with a card32 count
+        for &offset in &[hash_offset, directory_list_offset] {
+            if offset + 4 > self.data.len() {
+                return Err(CacheError::InvalidOffset { offset });
+            }
+        }
+
+        let n_buckets = self.card32(hash_offset).ok_or(CacheError::TruncatedData { offset: hash_offset })?;
+
+        // all buckets must be readable
+        if n_buckets == 0 || hash_offset + 4 + n_buckets * 4 > self.data.len() {
+            return Err(CacheError::CorruptHashTable);
+        }
+
+        let n_directories = self.card32(directory_list_offset)
+            .ok_or(CacheError::TruncatedData { offset: directory_list_offset })?;
+
+        // fields of one table must not be read as part of the other
+        let hash_end = hash_offset + 4 + n_buckets * 4;
+        let directory_list_end = directory_list_offset + 4 + n_directories * 4;
+        if hash_offset < directory_list_end && directory_list_offset < hash_end {
+            return Err(CacheError::OverlappingTables { hash_offset, directory_list_offset });
+        }
+
+        Ok(Header {
+            major_version: major_version as u16,
+            minor_version: minor_version as u16,
+            hash_offset,
+            directory_list_offset,
+            n_buckets,
+            n_directories,
+        })
+    }
+
+    ///
+    /// Call `f` with offsets of each entry and its name in a bucket chain,
+    /// until `f` returns `true` or the chain ends.
+    ///
+    pub fn walk_chain<F: FnMut(usize, usize) -> bool>(&self, hash_offset: usize, bucket_index: usize, mut f: F) {
+        // visited entries, only tracked for unusually long chains
+        let mut chain_len = 0;
+        let mut visited = None;
+
+        let mut entry_offset = match self.card32(hash_offset + 4 + bucket_index * 4) {
+            Some(offset) => offset,
+            None => return,
+        };
+
+        while entry_offset != EMPTY_OFFSET {
+            let name_offset = match self.card32(entry_offset + 4) {
+                Some(offset) => offset,
+                None => {
+                    warn!("bucket chain entry at offset {} is out of bounds", entry_offset);
+                    return;
+                }
+            };
+
+            chain_len += 1;
+            if chain_len > CHAIN_CHECK_THRESHOLD && !visited.get_or_insert_with(BTreeSet::new).insert(entry_offset) {
+                warn!("cycle in bucket chain at offset {}", entry_offset);
+                return;
+            }
+
+            if f(entry_offset, name_offset) {
+                return;
+            }
+
+            entry_offset = match self.card32(entry_offset) {
+                Some(offset) => offset,
+                None => return,
+            };
+        }
+    }
+
+    ///
+    /// Call `f` with the bucket index and offset of every entry in the hash
+    /// table, chain by chain. Each entry is visited once even if chains are
+    /// corrupted into cycles.
+    ///
+    pub fn walk_buckets<F: FnMut(usize, usize)>(&self, hash_offset: usize, n_buckets: usize, mut f: F) {
+        let mut visited = BTreeSet::new();
+
+        for bucket_index in 0..n_buckets {
+            let mut entry_offset = match self.card32(hash_offset + 4 + bucket_index * 4) {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            while entry_offset != EMPTY_OFFSET {
+                if entry_offset + 12 > self.data.len() {
+                    warn!("bucket chain entry at offset {} is out of bounds", entry_offset);
+                    break;
+                }
+
+                if !visited.insert(entry_offset) {
+                    break;
+                }
+
+                f(bucket_index, entry_offset);
+
+                entry_offset = match self.card32(entry_offset) {
+                    Some(offset) => offset,
+                    None => break,
+                };
+            }
+        }
+    }
+
+    ///
+    /// Check no chain is longer than `max_chain_length`, by walking the
+    /// whole hash table.
+    ///
+    pub fn check_chain_lengths(&self, header: &Header, max_chain_length: usize) -> Result<(), CacheError> {
+        let mut check = ChainLengthCheck::new(Some(max_chain_length));
+        self.walk_buckets(header.hash_offset, header.n_buckets, |bucket_index, _| { check.visit(bucket_index); });
+
+        check.finish(self, header)
+    }
+}
+
+///
+/// Finds the first chain longer than a limit while `Reader::walk_buckets`
+/// visits entries, so callers walking the table anyway need no extra pass.
+///
+pub(crate) struct ChainLengthCheck {
+    max_chain_length: Option<usize>,
+    last_bucket: Option<usize>,
+    chain_length: usize,
+    too_long: Option<usize>,
+}
+
+impl ChainLengthCheck {
+    pub fn new(max_chain_length: Option<usize>) -> Self {
+        Self {
+            max_chain_length,
+            last_bucket: None,
+            chain_length: 0,
+            too_long: None,
+        }
+    }
+
+    ///
+    /// Count an entry of `bucket_index`, returns `true` for every entry
+    /// after the first one of its chain.
+    ///
+    pub fn visit(&mut self, bucket_index: usize) -> bool {
+        let collision = self.last_bucket == Some(bucket_index);
+        self.chain_length = if collision { self.chain_length + 1 } else { 1 };
+        self.last_bucket = Some(bucket_index);
+
+        if self.too_long.is_none() && self.max_chain_length.is_some_and(|x| self.chain_length > x) {
+            self.too_long = Some(bucket_index);
+        }
+
+        collision
+    }
+
+    ///
+    /// Error for the first chain found too long, with its full length.
+    ///
+    pub fn finish(self, reader: &Reader, header: &Header) -> Result<(), CacheError> {
+        match self.too_long {
+            Some(bucket) => {
+                let mut length = 0;
+                reader.walk_chain(header.hash_offset, bucket, |_, _| { length += 1; false });
+
+                Err(CacheError::ChainTooLong { bucket, length })
+            }
+            None => Ok(()),
+        }
+    }
+}
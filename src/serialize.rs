@@ -0,0 +1,191 @@
+use GtkIconCache;
+use GtkIconCacheBuilder;
+use ImageTypeFlags;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer};
+
+use std::fmt;
+
+const FIELDS: &[&str] = &["directories", "icons"];
+
+///
+/// A cache is serialized as its decoded directories and icons, not its raw
+/// data, e.g. as JSON:
+///
+/// ```json
+/// {"directories": ["apps/16"], "icons": {"firefox": [["apps/16", 4]]}}
+/// ```
+///
+/// Image types are stored as `ImageTypeFlags::bits`. Names which are not
+/// valid UTF-8 are left out, like `GtkIconCache::sanitize` does.
+///
+impl Serialize for GtkIconCache {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let directories: Vec<_> = (0..self.n_directorys)
+            .filter_map(|x| self.utf8_directory_by_index(x))
+            .collect();
+
+        let mut state = serializer.serialize_struct("GtkIconCache", 2)?;
+        state.serialize_field("directories", &directories)?;
+        state.serialize_field("icons", &Icons(self.decoded_icons()))?;
+        state.end()
+    }
+}
+
+struct Icons<'a>(Vec<(&'a str, Vec<(&'a str, ImageTypeFlags)>)>);
+
+impl<'a> Serialize for Icons<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, dirs) in &self.0 {
+            map.serialize_entry(name, &Dirs(dirs))?;
+        }
+        map.end()
+    }
+}
+
+struct Dirs<'a>(&'a [(&'a str, ImageTypeFlags)]);
+
+impl<'a> Serialize for Dirs<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for &(dir, flags) in self.0 {
+            seq.serialize_element(&(dir, flags.bits()))?;
+        }
+        seq.end()
+    }
+}
+
+///
+/// A cache is deserialized by building a new in-memory cache with
+/// `GtkIconCacheBuilder`, so no file is needed.
+///
+impl<'de> Deserialize<'de> for GtkIconCache {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("GtkIconCache", FIELDS, CacheVisitor)
+    }
+}
+
+struct CacheVisitor;
+
+impl CacheVisitor {
+    fn build<E: de::Error>(directories: Vec<String>, icons: IconList) -> Result<GtkIconCache, E> {
+        let mut builder = GtkIconCacheBuilder::new();
+        for dir in &directories {
+            builder.add_directory(dir);
+        }
+
+        for (name, dirs) in icons.0 {
+            let dirs: Vec<_> = dirs.iter()
+                .map(|(dir, flags)| (builder.add_directory(dir), ImageTypeFlags::from_bits_truncate(*flags)))
+                .collect();
+            builder.add_icon(&name, &dirs);
+        }
+
+        GtkIconCache::with_bytes_owned(builder.build()).map_err(E::custom)
+    }
+}
+
+impl<'de> Visitor<'de> for CacheVisitor {
+    type Value = GtkIconCache;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct GtkIconCache")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let directories = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let icons = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+        Self::build(directories, icons)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut directories = None;
+        let mut icons = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "directories" if directories.is_none() => directories = Some(map.next_value()?),
+                "icons" if icons.is_none() => icons = Some(map.next_value()?),
+                "directories" | "icons" => return Err(de::Error::custom(format!("duplicate field `{}`", key))),
+                _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+            }
+        }
+
+        let directories = directories.ok_or_else(|| de::Error::missing_field("directories"))?;
+        let icons = icons.ok_or_else(|| de::Error::missing_field("icons"))?;
+
+        Self::build(directories, icons)
+    }
+}
+
+///
+/// Icons in serialized order, a map of icon name to `(directory, flags)`.
+///
+struct IconList(Vec<(String, Vec<(String, u16)>)>);
+
+impl<'de> Deserialize<'de> for IconList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(IconListVisitor)
+    }
+}
+
+struct IconListVisitor;
+
+impl<'de> Visitor<'de> for IconListVisitor {
+    type Value = IconList;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of icon names to directories")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut r = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(entry) = map.next_entry()? {
+            r.push(entry);
+        }
+
+        Ok(IconList(r))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use GtkIconCache;
+    use GtkIconCacheBuilder;
+    use ImageTypeFlags;
+
+    extern crate serde_json;
+
+    #[test]
+    fn test_serde_round_trip() {
+        let icon_cache = GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap();
+
+        let json = serde_json::to_string(&icon_cache).unwrap();
+        let decoded: GtkIconCache = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded.list_directories(), icon_cache.list_directories());
+        assert_eq!(decoded.to_hashmap(), icon_cache.to_hashmap());
+        assert_eq!(decoded.lookup_with_flags("firefox"), icon_cache.lookup_with_flags("firefox"));
+    }
+
+    #[test]
+    fn test_serde_json() {
+        let mut builder = GtkIconCacheBuilder::new();
+        let apps_16 = builder.add_directory("apps/16");
+        let apps_scalable = builder.add_directory("apps/scalable");
+        builder.add_icon("firefox", &[(apps_16, ImageTypeFlags::PNG), (apps_scalable, ImageTypeFlags::SVG)]);
+        let icon_cache = GtkIconCache::with_bytes_owned(builder.build()).unwrap();
+
+        assert_eq!(serde_json::to_string(&icon_cache).unwrap(),
+                   r#"{"directories":["apps/16","apps/scalable"],"icons":{"firefox":[["apps/16",4],["apps/scalable",2]]}}"#);
+
+        match serde_json::from_str::<GtkIconCache>(r#"{"directories":[]}"#) {
+            Err(e) => assert!(e.to_string().contains("missing field `icons`")),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use GtkIconCache;
+
+///
+/// Caches of several themes, looked up in the order they are added.
+///
+/// ```
+/// use gtk_icon_cache::*;
+///
+/// let mut set = GtkIconCacheSet::new();
+/// set.add_cache("test", GtkIconCache::with_file_path("test/caches/test1.cache").unwrap());
+/// set.add_cache("hicolor", GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap());
+///
+/// let (theme, _) = set.lookup("firefox").unwrap();
+/// assert_eq!(theme, "hicolor");
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct GtkIconCacheSet {
+    caches: Vec<(String, GtkIconCache)>,
+}
+
+impl GtkIconCacheSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Add a theme cache, it has lower priority than caches added before.
+    ///
+    /// * `theme_name` - name of the theme.
+    /// * `cache` - cache of the theme.
+    ///
+    pub fn add_cache(&mut self, theme_name: &str, cache: GtkIconCache) {
+        self.caches.push((theme_name.to_string(), cache));
+    }
+
+    ///
+    /// Look up an icon in the first theme containing it.
+    ///
+    /// * `icon_name` - icon name.
+    ///
+    pub fn lookup<T: AsRef<str>>(&self, icon_name: T) -> Option<(&str, Vec<&str>)> {
+        self.caches.iter()
+            .filter_map(|(theme, cache)| cache.lookup(icon_name.as_ref()).map(|x| (theme.as_str(), x)))
+            .next()
+    }
+
+    ///
+    /// Look up an icon in all themes containing it, in priority order.
+    ///
+    /// * `icon_name` - icon name.
+    ///
+    pub fn lookup_all<T: AsRef<str>>(&self, icon_name: T) -> Vec<(&str, Vec<&str>)> {
+        self.caches.iter()
+            .filter_map(|(theme, cache)| cache.lookup(icon_name.as_ref()).map(|x| (theme.as_str(), x)))
+            .collect()
+    }
+}
+
+///
+/// Look up an icon in the first cache containing it, for themes in priority
+/// order without building a `GtkIconCacheSet`.
+///
+/// * `caches` - caches of themes, highest priority first.
+/// * `icon_name` - icon name.
+///
+pub fn lookup_multiple_themes<'a>(caches: &'a [GtkIconCache], icon_name: &str) -> Option<(&'a GtkIconCache, Vec<&'a str>)> {
+    caches.iter()
+        .filter_map(|cache| cache.lookup(icon_name).map(|x| (cache, x)))
+        .next()
+}
+
+#[cfg(test)]
+mod test {
+
+    use GtkIconCache;
+    use GtkIconCacheSet;
+    use super::lookup_multiple_themes;
+
+    #[test]
+    fn test_cache_set() {
+        let mut set = GtkIconCacheSet::new();
+        set.add_cache("test", GtkIconCache::with_file_path("test/caches/test1.cache").unwrap());
+        set.add_cache("hicolor", GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap());
+        set.add_cache("fallback", GtkIconCache::with_file_path("test/caches/test1.cache").unwrap());
+
+        assert_eq!(set.lookup("test").unwrap().0, "test");
+        assert_eq!(set.lookup("firefox").unwrap().0, "hicolor");
+        assert!(set.lookup("not-exist").is_none());
+
+        let themes: Vec<_> = set.lookup_all("test").into_iter().map(|x| x.0).collect();
+        assert_eq!(themes, vec!["test", "fallback"]);
+    }
+
+    #[test]
+    fn test_lookup_multiple_themes() {
+        let caches = vec![GtkIconCache::with_file_path("test/caches/test1.cache").unwrap(),
+                          GtkIconCache::with_file_path("test/caches/icon-theme.cache").unwrap()];
+
+        let (cache, dirs) = lookup_multiple_themes(&caches, "firefox").unwrap();
+        assert!(::std::ptr::eq(cache, &caches[1]));
+        assert!(dirs.contains(&"apps/64"));
+
+        let (cache, _) = lookup_multiple_themes(&caches, "test").unwrap();
+        assert!(::std::ptr::eq(cache, &caches[0]));
+        assert!(lookup_multiple_themes(&caches, "not-exist").is_none());
+        assert!(lookup_multiple_themes(&[], "firefox").is_none());
+    }
+}
@@ -0,0 +1,38 @@
+///
+/// Statistics of a cache, see `GtkIconCache::stats`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    /// Number of buckets in the hash table.
+    pub n_buckets: usize,
+    /// Number of icons.
+    pub n_icons: usize,
+    /// Number of directories in the directory list.
+    pub n_directories: usize,
+    /// Number of buckets without icons.
+    pub empty_buckets: usize,
+    /// Number of icons in the longest bucket chain.
+    pub max_chain_length: usize,
+    /// Average number of icons in non-empty bucket chains.
+    pub avg_chain_length: f64,
+    /// Size of the cache data.
+    pub file_size_bytes: usize,
+}
+
+impl CacheStats {
+    pub(crate) fn new(chain_lengths: &[usize], n_directories: usize, file_size_bytes: usize) -> Self {
+        let n_icons = chain_lengths.iter().sum();
+        let empty_buckets = chain_lengths.iter().filter(|&&x| x == 0).count();
+        let non_empty_buckets = chain_lengths.len() - empty_buckets;
+
+        CacheStats {
+            n_buckets: chain_lengths.len(),
+            n_icons,
+            n_directories,
+            empty_buckets,
+            max_chain_length: chain_lengths.iter().cloned().max().unwrap_or(0),
+            avg_chain_length: if non_empty_buckets == 0 { 0.0 } else { n_icons as f64 / non_empty_buckets as f64 },
+            file_size_bytes,
+        }
+    }
+}
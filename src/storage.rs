@@ -0,0 +1,32 @@
+use memmap2::Mmap;
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+///
+/// The bytes a cache is parsed from.
+///
+#[derive(Debug, Clone)]
+pub enum BackingStorage {
+    Mmap(Arc<Mmap>),
+    Bytes(Arc<Vec<u8>>),
+    Static(&'static [u8]),
+}
+
+impl BackingStorage {
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            BackingStorage::Mmap(ref m) => m,
+            BackingStorage::Bytes(ref b) => b,
+            BackingStorage::Static(s) => s,
+        }
+    }
+}
+
+impl Deref for BackingStorage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
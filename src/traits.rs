@@ -0,0 +1,67 @@
+#[cfg(feature = "std")]
+use GtkIconCache;
+
+use alloc::vec::Vec;
+
+///
+/// Lookups shared by `GtkIconCache` and `GtkIconCacheRef`.
+///
+/// ```
+/// use gtk_icon_cache::*;
+///
+/// fn has_firefox<C: IconCache>(cache: &C) -> bool {
+///     cache.contains("firefox")
+/// }
+///
+/// let data = std::fs::read("test/caches/icon-theme.cache").unwrap();
+/// assert!(has_firefox(&GtkIconCacheRef::with_bytes(&data).unwrap()));
+/// # #[cfg(feature = "std")]
+/// assert!(has_firefox(&GtkIconCache::with_bytes(&data).unwrap()));
+/// ```
+///
+pub trait IconCache {
+    ///
+    /// Look up an icon, returning directories containing it.
+    ///
+    /// * `name` - icon name.
+    ///
+    fn lookup(&self, name: &str) -> Option<Vec<&str>>;
+
+    ///
+    /// List all directories of the cache, in index order.
+    ///
+    fn list_directories(&self) -> Vec<&str>;
+
+    ///
+    /// Number of buckets in the hash table.
+    ///
+    fn n_buckets(&self) -> usize;
+
+    ///
+    /// Check if an icon is in the cache.
+    ///
+    /// * `name` - icon name.
+    ///
+    fn contains(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+}
+
+#[cfg(feature = "std")]
+impl IconCache for GtkIconCache {
+    fn lookup(&self, name: &str) -> Option<Vec<&str>> {
+        GtkIconCache::lookup(self, name)
+    }
+
+    fn list_directories(&self) -> Vec<&str> {
+        GtkIconCache::list_directories(self)
+    }
+
+    fn n_buckets(&self) -> usize {
+        GtkIconCache::n_buckets(self)
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        GtkIconCache::contains(self, name)
+    }
+}
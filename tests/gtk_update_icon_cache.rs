@@ -0,0 +1,108 @@
+//!
+//! Compare parsing against caches written by `gtk-update-icon-cache`.
+//!
+//! Run with `cargo test --features integration-tests`, tests are skipped
+//! when `gtk-update-icon-cache` is not in `PATH`.
+//!
+
+#![cfg(feature = "integration-tests")]
+
+extern crate gtk_icon_cache;
+
+use gtk_icon_cache::*;
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+///
+/// Temporary theme directory, removed when dropped so skipped or failed
+/// tests do not leave it behind.
+///
+struct ThemeDir(PathBuf);
+
+impl Deref for ThemeDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ThemeDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn theme_dir(name: &str) -> ThemeDir {
+    let dir = env::temp_dir().join(format!("gtk-icon-cache-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    for &(sub_dir, file) in &[("apps/16", "firefox.png"),
+                              ("apps/48", "firefox.png"),
+                              ("apps/48", "web-browser.png"),
+                              ("apps/scalable", "firefox.svg"),
+                              ("places/32", "folder.png")] {
+        fs::create_dir_all(dir.join(sub_dir)).unwrap();
+        fs::write(dir.join(sub_dir).join(file), b"").unwrap();
+    }
+
+    ThemeDir(dir)
+}
+
+fn update_icon_cache(theme_dir: &Path) -> bool {
+    let status = Command::new("gtk-update-icon-cache")
+        .args(["--force", "--ignore-theme-index", "--quiet"])
+        .arg(theme_dir)
+        .status();
+
+    match status {
+        Ok(status) => {
+            assert!(status.success(), "gtk-update-icon-cache failed: {}", status);
+            true
+        }
+        Err(_) => {
+            eprintln!("gtk-update-icon-cache not found, skipped");
+            false
+        }
+    }
+}
+
+#[test]
+fn test_gtk_update_icon_cache() {
+    let dir = theme_dir("integration");
+    if !update_icon_cache(&dir) {
+        return;
+    }
+
+    let icon_cache = GtkIconCache::from_theme_dir(&*dir).unwrap();
+    assert!(icon_cache.validate().is_ok());
+    assert_eq!(icon_cache.icon_count(), 3);
+
+    let mut dirs = icon_cache.list_directories();
+    dirs.sort();
+    assert_eq!(dirs, vec!["apps/16", "apps/48", "apps/scalable", "places/32"]);
+
+    let mut firefox = icon_cache.lookup("firefox").unwrap();
+    firefox.sort();
+    assert_eq!(firefox, vec!["apps/16", "apps/48", "apps/scalable"]);
+    assert_eq!(icon_cache.lookup_svg_only("firefox").unwrap(), vec!["apps/scalable"]);
+    assert_eq!(icon_cache.lookup("folder").unwrap(), vec!["places/32"]);
+    assert_eq!(icon_cache.lookup("not-exist"), None);
+}
+
+#[test]
+fn test_rebuild_matches_gtk_update_icon_cache() {
+    let dir = theme_dir("rebuild");
+    if !update_icon_cache(&dir) {
+        return;
+    }
+
+    let gtk_cache = GtkIconCache::from_theme_dir(&*dir).unwrap();
+    let rebuilt = GtkIconCacheBuilder::rebuild_from_directory(&*dir).unwrap();
+
+    assert_eq!(diff(&gtk_cache, &rebuilt), CacheDiff::default());
+}